@@ -0,0 +1,53 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use fast_regex::regex::{build_plan::ImplicitRe, Regex};
+
+/// A name, a pattern, and how to make an input of a given length for it.
+type Case = (&'static str, Regex<'static>, fn(usize) -> String);
+
+/// Patterns that are known to be hard on derivative engines.
+fn cases() -> Vec<Case> {
+    let words = [
+        "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta",
+    ];
+    let alternation = (0..64)
+        .map(|i| format!("{}{}", words[i % words.len()], i))
+        .fold(None, |acc, w| match acc {
+            None => Some(w.re()),
+            Some(acc) => Some(acc.alt(w.as_str())),
+        })
+        .unwrap();
+    let nested = (0..8).fold('a'.re(), |acc, _| acc.alt('b').star().seq('c'));
+    vec![
+        ("(a*)*b", Regex::from(&'a'.star().star().seq('b')), |n| {
+            "a".repeat(n)
+        }),
+        ("long alternation", Regex::from(&alternation.star()), |n| {
+            "theta7".repeat(n / 6 + 1)[..n].to_string()
+        }),
+        ("deep nesting", Regex::from(&nested), |n| {
+            "ab".repeat(n / 2) + "c"
+        }),
+    ]
+}
+
+fn pathological(c: &mut Criterion) {
+    let lengths = [10, 100, 1000];
+    let mut group = c.benchmark_group("pathological");
+    for (name, r, input) in cases() {
+        for &n in &lengths {
+            let s = input(n);
+            // the step count and the size of the final state don't depend on the machine, so they
+            // are worth keeping an eye on along with the time
+            let (_, steps) = r.is_match_counting(&s);
+            let nodes = r.der_str(&s).node_count();
+            println!("{name} n={n}: {steps} steps, {nodes} nodes at the end");
+            group.bench_with_input(BenchmarkId::new(name, n), &s, |b, s| {
+                b.iter(|| r.is_match(s))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, pathological);
+criterion_main!(benches);
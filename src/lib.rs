@@ -0,0 +1,4 @@
+#![feature(allocator_api, slice_ptr_get)]
+
+pub mod regex;
+pub mod vec_alloc;
@@ -1,11 +1 @@
-#![feature(
-    vec_push_within_capacity,
-    allocator_api,
-    alloc_layout_extra,
-    slice_ptr_get
-)]
-
-mod regex;
-mod vec_alloc;
-
 fn main() {}
@@ -0,0 +1,97 @@
+/// A sequence of inclusive class ranges, one per symbol, see `Alphabet::encode_any`.
+pub type Spelling<C> = Vec<(C, C)>;
+
+/// What a `Regex` is matched against. The derivative engine only ever compares input with the
+/// pattern through `matches`, so `der`, `simp` and `nullable` work the same for every alphabet.
+pub trait Alphabet: Copy + 'static {
+    /// One unit of input.
+    type Symbol: Copy + Eq + std::hash::Hash;
+    /// What a `Re::Char` node holds, i.e. which `Symbol`s one step of the pattern accepts.
+    type Class: Copy + Ord + std::hash::Hash + std::fmt::Debug + Send + Sync;
+
+    fn matches(class: &Self::Class, symbol: Self::Symbol) -> bool;
+
+    /// Whether `symbol` is in the inclusive range of classes `lo..=hi`, see `Re::Range`.
+    fn in_range(lo: &Self::Class, hi: &Self::Class, symbol: Self::Symbol) -> bool;
+
+    /// Spells a pattern `char` as the classes that match it, in order.
+    fn encode(c: char) -> impl Iterator<Item = Self::Class>;
+
+    /// Spells the pattern range `lo..=hi` as a single range of classes, if there is one.
+    fn encode_range(lo: char, hi: char) -> Option<(Self::Class, Self::Class)>;
+
+    /// Spells 'any `char`' as alternatives, each a sequence of class ranges. `None` if every
+    /// symbol is a whole `char`, so that `Re::Any` already means the same thing.
+    fn encode_any() -> Option<Vec<Spelling<Self::Class>>>;
+}
+
+/// Matches `char` by `char`. This is the default alphabet.
+#[derive(Debug, Clone, Copy)]
+pub struct Chars;
+
+impl Alphabet for Chars {
+    type Symbol = char;
+    type Class = char;
+
+    fn matches(class: &char, symbol: char) -> bool {
+        *class == symbol
+    }
+
+    fn in_range(lo: &char, hi: &char, symbol: char) -> bool {
+        (*lo..=*hi).contains(&symbol)
+    }
+
+    fn encode(c: char) -> impl Iterator<Item = char> {
+        std::iter::once(c)
+    }
+
+    fn encode_range(lo: char, hi: char) -> Option<(char, char)> {
+        Some((lo, hi))
+    }
+
+    fn encode_any() -> Option<Vec<Spelling<char>>> {
+        None
+    }
+}
+
+/// Matches byte by byte. Pattern `char`s are spelled as their UTF-8 encoding, so matching the
+/// bytes of a `str` gives the same result as matching its `char`s with `Chars`.
+#[derive(Debug, Clone, Copy)]
+pub struct Bytes;
+
+impl Alphabet for Bytes {
+    type Symbol = u8;
+    type Class = u8;
+
+    fn matches(class: &u8, symbol: u8) -> bool {
+        *class == symbol
+    }
+
+    fn in_range(lo: &u8, hi: &u8, symbol: u8) -> bool {
+        (*lo..=*hi).contains(&symbol)
+    }
+
+    fn encode(c: char) -> impl Iterator<Item = u8> {
+        let mut buf = [0; 4];
+        let len = c.encode_utf8(&mut buf).len();
+        buf.into_iter().take(len)
+    }
+
+    /// Only ASCII ranges are a single range of bytes, anything else has to be spelled `char` by
+    /// `char`.
+    fn encode_range(lo: char, hi: char) -> Option<(u8, u8)> {
+        hi.is_ascii().then_some((lo as u8, hi as u8))
+    }
+
+    /// Every UTF-8 sequence of one to four bytes, by its leading byte. This also accepts some
+    /// invalid sequences (e.g. overlong ones), which never turn up in the bytes of a `str`.
+    fn encode_any() -> Option<Vec<Spelling<u8>>> {
+        const CONT: (u8, u8) = (0x80, 0xBF);
+        Some(vec![
+            vec![(0x00, 0x7F)],
+            vec![(0xC2, 0xDF), CONT],
+            vec![(0xE0, 0xEF), CONT, CONT],
+            vec![(0xF0, 0xF4), CONT, CONT, CONT],
+        ])
+    }
+}
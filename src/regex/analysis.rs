@@ -0,0 +1,434 @@
+use std::collections::BTreeSet;
+
+use super::*;
+
+/// Patterns with more nodes than this are reported as `Issue::Large`.
+pub const LARGE_PATTERN: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Safe,
+    Moderate,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Issue {
+    /// A star inside another star, like `(a*)*`.
+    NestedStar,
+    /// An alternation where both branches can start with the same `char`, like `(ab|ac)`.
+    AmbiguousAlt,
+    /// More than `LARGE_PATTERN` nodes, e.g. from a large bounded repetition.
+    Large(usize),
+}
+
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Issue::NestedStar => Severity::High,
+            Issue::AmbiguousAlt | Issue::Large(_) => Severity::Moderate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComplexityReport {
+    pub severity: Severity,
+    /// The worst issue found, and the subexpression it was found in, as printed by `Debug`. For
+    /// `Issue::Large`, which is about the whole pattern, this is a short summary of it instead,
+    /// since the pattern can be too big to print.
+    pub worst: Option<(Issue, String)>,
+}
+
+/// Facts about a `Regex` that only depend on its tree, so that they can be computed once and then
+/// reused by every match.
+#[derive(Debug, Clone)]
+pub(super) struct Analyses<A: Alphabet> {
+    /// The fewest and most symbols a match can have, `None` for no maximum. `None` overall if
+    /// nothing matches at all.
+    pub(super) length_bounds: Option<(usize, Option<usize>)>,
+    /// `None` if a match could start with (almost) anything.
+    pub(super) first_set: Option<BTreeSet<A::Class>>,
+    /// Classes that every match starts with.
+    pub(super) required_prefix: Vec<A::Class>,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// How many times `Analyses` have been computed on this thread.
+    pub(super) static ANALYSES_COMPUTED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+impl<A: Alphabet> Re<A> {
+    /// What kind of node this is, for messages about patterns too big to print.
+    fn kind(&self) -> &'static str {
+        match self {
+            Re::Zero => "a Zero",
+            Re::One => "a One",
+            Re::Char(_) => "a Char",
+            Re::Range(..) => "a Range",
+            Re::Any => "an Any",
+            Re::AnyOf(..) => "an AnyOf",
+            Re::NotAnyOf(..) => "a NotAnyOf",
+            Re::Alt(..) => "an Alt",
+            Re::Seq(..) => "a Seq",
+            Re::And(..) => "an And",
+            Re::Star(_) => "a Star",
+            Re::Plus(_) => "a Plus",
+            Re::Opt(_) => "an Opt",
+            Re::Not(_) => "a Not",
+            Re::Mark(..) => "a Mark",
+        }
+    }
+
+    /// Computes `f` for every node reachable from `self`, children first, and returns the value
+    /// for `self`. `f` gets each node along with the values of its children, left to right.
+    /// Patterns can be far deeper than the call stack, so like `build_limited`, this is a
+    /// post-order traversal with an explicit stack.
+    ///
+    /// ## Safety
+    /// Every node reachable from `self` must be valid for reads.
+    unsafe fn fold_post<T>(&self, mut f: impl FnMut(&Re<A>, Vec<T>) -> T) -> T {
+        enum Frame<A: Alphabet> {
+            Enter(Const<Re<A>>),
+            /// The values of the node's children are on top of the `values` stack.
+            Exit(Const<Re<A>>, usize),
+        }
+
+        let mut stack = vec![Frame::Enter(Const::from(self))];
+        let mut values = Vec::new();
+        while let Some(frame) = stack.pop() {
+            match frame {
+                Frame::Enter(r) => match r.read() {
+                    Re::Alt(r1, r2) | Re::Seq(r1, r2) | Re::And(r1, r2) => {
+                        stack.push(Frame::Exit(r, 2));
+                        stack.push(Frame::Enter(r2));
+                        stack.push(Frame::Enter(r1));
+                    }
+                    Re::Star(r1) | Re::Plus(r1) | Re::Opt(r1) | Re::Not(r1) | Re::Mark(_, r1) => {
+                        stack.push(Frame::Exit(r, 1));
+                        stack.push(Frame::Enter(r1));
+                    }
+                    node => values.push(f(&node, Vec::new())),
+                },
+                Frame::Exit(r, n) => {
+                    let children = values.split_off(values.len() - n);
+                    values.push(f(r.as_ref(), children));
+                }
+            }
+        }
+        values.pop().unwrap()
+    }
+
+    /// Adds every symbol a non-empty match of `self` can start with to `set`, or sets it to `None`
+    /// if there are too many of them to list.
+    ///
+    /// ## Safety
+    /// Every node reachable from `self` must be valid for reads.
+    unsafe fn first_set_into(&self, set: &mut Option<BTreeSet<A::Class>>) {
+        enum Frame<'r, A: Alphabet> {
+            Visit(&'r Re<A>),
+            /// Start a new set, for one side of an `And`.
+            Open,
+            /// The sets of both sides of an `And` are on top of `sets`, so merge them.
+            Intersect,
+        }
+
+        // Like `nullable`, this uses an explicit stack, since patterns can be very deep. Every
+        // node adds to the set on top of `sets`, and each side of an `And` gets its own.
+        let mut sets = vec![set.take()];
+        let mut stack = vec![Frame::Visit(self)];
+        // SAFETY (for every `as_ref`): guaranteed by the caller
+        while let Some(frame) = stack.pop() {
+            let r = match frame {
+                Frame::Visit(r) => r,
+                Frame::Open => {
+                    sets.push(Some(BTreeSet::new()));
+                    continue;
+                }
+                Frame::Intersect => {
+                    let first2 = sets.pop().unwrap();
+                    let first1 = sets.pop().unwrap();
+                    // a match has to be able to start both sides
+                    let both = match (first1, first2) {
+                        (Some(first1), Some(first2)) => {
+                            Some(first1.intersection(&first2).copied().collect())
+                        }
+                        (Some(first), None) | (None, Some(first)) => Some(first),
+                        (None, None) => None,
+                    };
+                    let set = sets.last_mut().unwrap();
+                    match (set.as_mut(), both) {
+                        (Some(chars), Some(both)) => chars.extend(both),
+                        _ => *set = None,
+                    }
+                    continue;
+                }
+            };
+            let set = sets.last_mut().unwrap();
+            let Some(chars) = set else {
+                continue;
+            };
+            match r {
+                Re::Zero | Re::One => {}
+                Re::Char(c) => {
+                    chars.insert(*c);
+                }
+                Re::AnyOf(set, len) => chars.extend(any_of(*set, *len)),
+                // a range can start with too many symbols to list them all
+                Re::Range(..) | Re::Any | Re::NotAnyOf(..) => *set = None,
+                // a complement can start with anything its inner pattern can't rule out
+                Re::Not(_) => *set = None,
+                Re::Alt(r1, r2) => unsafe {
+                    stack.push(Frame::Visit(r2.as_ref()));
+                    stack.push(Frame::Visit(r1.as_ref()));
+                },
+                Re::Seq(r1, r2) => unsafe {
+                    if r1.as_ref().nullable() {
+                        stack.push(Frame::Visit(r2.as_ref()));
+                    }
+                    stack.push(Frame::Visit(r1.as_ref()));
+                },
+                Re::And(r1, r2) => unsafe {
+                    stack.push(Frame::Intersect);
+                    stack.push(Frame::Visit(r2.as_ref()));
+                    stack.push(Frame::Open);
+                    stack.push(Frame::Visit(r1.as_ref()));
+                    stack.push(Frame::Open);
+                },
+                Re::Star(r) | Re::Plus(r) | Re::Opt(r) | Re::Mark(_, r) => {
+                    stack.push(Frame::Visit(unsafe { r.as_ref() }))
+                }
+            }
+        }
+        *set = sets.pop().unwrap();
+    }
+
+    /// ## Safety
+    /// Every node reachable from `self` must be valid for reads.
+    unsafe fn length_bounds(&self) -> Option<(usize, Option<usize>)> {
+        self.fold_post(|r, bounds: Vec<Option<(usize, Option<usize>)>>| match r {
+            Re::Zero => None,
+            Re::One => Some((0, Some(0))),
+            Re::Char(_) | Re::Range(..) | Re::Any | Re::AnyOf(..) | Re::NotAnyOf(..) => {
+                Some((1, Some(1)))
+            }
+            Re::Alt(..) => match (bounds[0], bounds[1]) {
+                (None, b) | (b, None) => b,
+                (Some((min1, max1)), Some((min2, max2))) => {
+                    Some((min1.min(min2), max1.zip(max2).map(|(m1, m2)| m1.max(m2))))
+                }
+            },
+            Re::Seq(..) => {
+                let (min1, max1) = bounds[0]?;
+                let (min2, max2) = bounds[1]?;
+                let max = max1.zip(max2).and_then(|(m1, m2)| m1.checked_add(m2));
+                Some((min1.saturating_add(min2), max))
+            }
+            Re::And(..) => {
+                let (min1, max1) = bounds[0]?;
+                let (min2, max2) = bounds[1]?;
+                let min = min1.max(min2);
+                let max = match (max1, max2) {
+                    (Some(m1), Some(m2)) => Some(m1.min(m2)),
+                    (m, None) | (None, m) => m,
+                };
+                // the lengths the two sides can have don't overlap
+                if max.is_some_and(|max| max < min) {
+                    return None;
+                }
+                Some((min, max))
+            }
+            // whether this is empty depends on whether `r` matches everything, so be conservative
+            Re::Not(_) => Some((0, None)),
+            Re::Star(_) => match bounds[0] {
+                None | Some((_, Some(0))) => Some((0, Some(0))),
+                Some(_) => Some((0, None)),
+            },
+            Re::Plus(_) => match bounds[0]? {
+                (_, Some(0)) => Some((0, Some(0))),
+                (min, _) => Some((min, None)),
+            },
+            Re::Opt(_) => match bounds[0] {
+                None => Some((0, Some(0))),
+                Some((_, max)) => Some((0, max)),
+            },
+            Re::Mark(..) => bounds[0],
+        })
+    }
+
+    /// The classes every match starts with, and whether that is the only thing that matches.
+    ///
+    /// ## Safety
+    /// Every node reachable from `self` must be valid for reads.
+    unsafe fn required_prefix(&self) -> (Vec<A::Class>, bool) {
+        // The prefixes are built back to front, so that a `Seq` can add the prefix of its left
+        // side to the end of its right side's. Going the other way would copy a long spine's
+        // prefix once for every `Seq` on it.
+        let (mut prefix, exact) = self.fold_post(|r, prefixes: Vec<(Vec<A::Class>, bool)>| {
+            let mut prefixes = prefixes.into_iter();
+            let mut next = || prefixes.next().unwrap();
+            match r {
+                // nothing to require of a match that can't happen, but be conservative
+                Re::Zero | Re::Star(_) | Re::Opt(_) | Re::Not(_) => (Vec::new(), false),
+                Re::Range(..) | Re::Any | Re::AnyOf(..) | Re::NotAnyOf(..) => (Vec::new(), false),
+                Re::One => (Vec::new(), true),
+                Re::Char(c) => (vec![*c], true),
+                Re::Mark(..) => next(),
+                Re::Alt(..) => {
+                    let (mut p1, exact1) = next();
+                    let (p2, exact2) = next();
+                    let common = (p1.iter().rev())
+                        .zip(p2.iter().rev())
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    let exact = exact1 && exact2 && p1 == p2;
+                    p1.drain(..p1.len() - common);
+                    (p1, exact)
+                }
+                // every match starts with a match of `r`, but it might not stop there
+                Re::Plus(_) => (next().0, false),
+                // every match has to start with both prefixes, so the longer one is enough
+                Re::And(..) => {
+                    let (p1, _) = next();
+                    let (p2, _) = next();
+                    (if p1.len() >= p2.len() { p1 } else { p2 }, false)
+                }
+                Re::Seq(..) => {
+                    let (p1, exact1) = next();
+                    let (mut p2, exact2) = next();
+                    if !exact1 {
+                        return (p1, false);
+                    }
+                    p2.extend(p1);
+                    (p2, exact2)
+                }
+            }
+        });
+        prefix.reverse();
+        (prefix, exact)
+    }
+}
+
+impl<'a, A: Alphabet> Regex<'a, A> {
+    pub(super) fn analyses(&self) -> &Analyses<A> {
+        self.analyses.get_or_init(|| {
+            #[cfg(test)]
+            ANALYSES_COMPUTED.with(|n| n.set(n.get() + 1));
+            // SAFETY: the tree is owned by this `Regex` or its parents
+            let tree = unsafe { self.tree.as_ref() };
+            let mut first_set = Some(BTreeSet::new());
+            unsafe {
+                tree.first_set_into(&mut first_set);
+                Analyses {
+                    length_bounds: tree.length_bounds(),
+                    first_set,
+                    required_prefix: tree.required_prefix().0,
+                }
+            }
+        })
+    }
+
+    /// Every symbol that a non-empty match can start with, or `None` if that could be (almost)
+    /// anything, e.g. for a complement.
+    pub fn first_set(&self) -> Option<&BTreeSet<A::Class>> {
+        self.analyses().first_set.as_ref()
+    }
+
+    /// The fewest and most symbols a match can have, with `None` for no maximum. Returns `None` if
+    /// nothing matches at all. The bounds are conservative, so a `Some` doesn't mean that
+    /// something does match.
+    pub fn length_bounds(&self) -> Option<(usize, Option<usize>)> {
+        self.analyses().length_bounds
+    }
+
+    /// The symbols that every match starts with.
+    pub fn required_prefix(&self) -> &[A::Class] {
+        &self.analyses().required_prefix
+    }
+}
+
+impl<'a> Regex<'a> {
+    /// Looks for structures that are known to make derivatives grow quickly, so that patterns
+    /// can be linted before they are used. This is a heuristic, a `Safe` pattern can still be slow
+    /// and a `High` one can be fine.
+    pub fn analyze_complexity(&self) -> ComplexityReport {
+        let mut worst: Option<(Issue, String)> = None;
+        // only describe an issue if it is going to be kept
+        let mut report = |issue: Issue, describe: &dyn Fn() -> String| {
+            if worst
+                .as_ref()
+                .is_none_or(|(w, _)| w.severity() < issue.severity())
+            {
+                worst = Some((issue, describe()));
+            }
+        };
+
+        let mut nodes = 0;
+        // (node, the outermost star it is inside of)
+        let mut stack: Vec<(Const<Re>, Option<Const<Re>>)> = vec![(self.tree, None)];
+        while let Some((r, in_star)) = stack.pop() {
+            nodes += 1;
+            // SAFETY: the tree is owned by this `Regex` or its parents
+            let r = unsafe { r.as_ref() };
+            match r {
+                Re::Zero
+                | Re::One
+                | Re::Char(_)
+                | Re::Range(..)
+                | Re::Any
+                | Re::AnyOf(..)
+                | Re::NotAnyOf(..) => {}
+                Re::Alt(r1, r2) => {
+                    let mut first1 = Some(BTreeSet::new());
+                    let mut first2 = Some(BTreeSet::new());
+                    // SAFETY: as above
+                    unsafe {
+                        r1.as_ref().first_set_into(&mut first1);
+                        r2.as_ref().first_set_into(&mut first2);
+                    }
+                    let ambiguous = match (first1, first2) {
+                        (Some(first1), Some(first2)) => !first1.is_disjoint(&first2),
+                        (Some(first), None) | (None, Some(first)) => !first.is_empty(),
+                        (None, None) => true,
+                    };
+                    if ambiguous {
+                        report(Issue::AmbiguousAlt, &|| format!("{:?}", r));
+                    }
+                    stack.push((*r1, in_star));
+                    stack.push((*r2, in_star));
+                }
+                Re::Seq(r1, r2) | Re::And(r1, r2) => {
+                    stack.push((*r1, in_star));
+                    stack.push((*r2, in_star));
+                }
+                Re::Opt(r) | Re::Not(r) | Re::Mark(_, r) => stack.push((*r, in_star)),
+                Re::Star(inner) | Re::Plus(inner) => match in_star {
+                    Some(outer) => {
+                        // SAFETY: as above
+                        report(Issue::NestedStar, &|| {
+                            format!("{:?}", unsafe { outer.as_ref() })
+                        });
+                        stack.push((*inner, in_star));
+                    }
+                    None => stack.push((*inner, Some(Const::from(r)))),
+                },
+            }
+        }
+
+        if nodes > LARGE_PATTERN {
+            // SAFETY: as above
+            let root = unsafe { self.tree.as_ref() }.kind();
+            report(Issue::Large(nodes), &|| {
+                format!("{} nodes, with {} at the root", nodes, root)
+            });
+        }
+
+        ComplexityReport {
+            severity: worst
+                .as_ref()
+                .map_or(Severity::Safe, |(issue, _)| issue.severity()),
+            worst,
+        }
+    }
+}
@@ -0,0 +1,40 @@
+/// A zero-width assertion about whether we are at the edge of a word, like `\b` and `\B`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    /// `\b`, a word `char` on exactly one side.
+    Word,
+    /// `\B`, a word `char` on both sides or on neither.
+    NotWord,
+}
+
+/// Whether `c` is part of a word, i.e. alphanumeric (in the Unicode sense) or `_`.
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Boundary {
+    /// Checks the assertion between `before` and `after`, where `None` is the start or end of the
+    /// input.
+    pub fn holds(self, before: Option<char>, after: Option<char>) -> bool {
+        let edge = before.is_some_and(is_word_char) != after.is_some_and(is_word_char);
+        match self {
+            Boundary::Word => edge,
+            Boundary::NotWord => !edge,
+        }
+    }
+}
+
+/// The assertions a pattern makes around its matches. Boundaries are only supported at the very
+/// start and end of a pattern, so these are checked by the search loop, not by the derivatives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Boundaries {
+    pub start: Option<Boundary>,
+    pub end: Option<Boundary>,
+}
+
+impl Boundaries {
+    /// Checks `boundary` at byte `i` of `s`.
+    pub(super) fn check(boundary: Option<Boundary>, s: &str, i: usize) -> bool {
+        boundary.is_none_or(|b| b.holds(s[..i].chars().next_back(), s[i..].chars().next()))
+    }
+}
@@ -0,0 +1,50 @@
+use super::*;
+
+/// The outcome of `Regex::match_with_budget`.
+#[derive(Debug, PartialEq)]
+pub enum BudgetResult {
+    Matched,
+    NotMatched,
+    /// The budget ran out after `consumed` bytes of the input. Matching the rest of the input
+    /// against `resumable_state` (e.g. with another `match_with_budget`) gives the final answer.
+    Exhausted {
+        consumed: usize,
+        resumable_state: Regex<'static>,
+    },
+}
+
+impl<'a> Regex<'a> {
+    /// Like `is_match`, but derives by at most `max_steps` chars. If that isn't enough to get to
+    /// the end of `s`, this returns how far it got, along with the state to carry on from.
+    pub fn match_with_budget(&self, s: &str, max_steps: usize) -> BudgetResult {
+        if !Boundaries::check(self.boundaries.start, s, 0) {
+            return BudgetResult::NotMatched;
+        }
+
+        let mut r = self.clone();
+        for (steps, (i, c)) in s.char_indices().enumerate() {
+            if steps == max_steps {
+                // the start boundary has been checked already, but the end one still needs to be
+                let boundaries = Boundaries {
+                    start: None,
+                    end: self.boundaries.end,
+                };
+                return BudgetResult::Exhausted {
+                    consumed: i,
+                    resumable_state: r.with_boundaries(boundaries),
+                };
+            }
+            r = r.der(self.normalize(c)).simp().clone();
+            // SAFETY: dereferencing a reference to immutable buffers
+            if let Re::Zero = unsafe { r.tree.as_ref() } {
+                return BudgetResult::NotMatched;
+            }
+        }
+
+        if r.nullable() && Boundaries::check(self.boundaries.end, s, s.len()) {
+            BudgetResult::Matched
+        } else {
+            BudgetResult::NotMatched
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::{error, fmt};
+
+use super::build_plan;
+
+/// Why `Regex::try_from_plan` couldn't build a plan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// Building would take more than `limit` nodes, see `Regex::try_from_plan_with_limit`.
+    CapacityExceeded { limit: usize },
+    /// A `Range` with `lo > hi`, which `build_plan::Re::range` would have refused.
+    InvalidRange(char, char),
+    /// An `AnyOf` or `NotAnyOf` whose `char`s aren't sorted and deduplicated, which
+    /// `build_plan::Re::one_of` and `none_of` take care of.
+    UnsortedSet,
+}
+
+impl BuildError {
+    /// Finds the first node of `plan` that can't be built as it is. Plans made with the
+    /// constructors in `build_plan` always pass, but the variants can be made by hand (or
+    /// deserialized) too.
+    pub(super) fn check(plan: &build_plan::Re) -> Result<(), BuildError> {
+        let mut stack = vec![plan];
+        while let Some(r) = stack.pop() {
+            match r {
+                build_plan::Re::Range(lo, hi) if lo > hi => {
+                    return Err(BuildError::InvalidRange(*lo, *hi))
+                }
+                build_plan::Re::AnyOf(chars) | build_plan::Re::NotAnyOf(chars)
+                    if chars.windows(2).any(|w| w[0] >= w[1]) =>
+                {
+                    return Err(BuildError::UnsortedSet)
+                }
+                _ => stack.extend(r.children()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CapacityExceeded { limit } => {
+                write!(f, "building takes more than the limit of {} nodes", limit)
+            }
+            Self::InvalidRange(lo, hi) => write!(f, "invalid range {:?}-{:?}", lo, hi),
+            Self::UnsortedSet => write!(f, "set of chars is not sorted and deduplicated"),
+        }
+    }
+}
+
+impl error::Error for BuildError {}
@@ -1,17 +1,418 @@
+use std::{fmt, ops};
+
+use super::Regex;
+
 /// Maps one-to-one with `regex::Re`, but provides a safe way of constructing proper `Regex`
+///
+/// Unlike `regex::Re`, this owns all of its children, so with the `serde` feature it can be
+/// serialized, and deserialized back into a plan to build a `Regex` from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Re {
     One,
     Zero,
     Char(char),
+    /// Any one `char` in `lo..=hi`.
+    Range(char, char),
+    /// Any one `char`.
+    Any,
+    /// Any one of these `char`s, sorted and without duplicates.
+    AnyOf(Vec<char>),
+    /// Any one `char` but these, sorted and without duplicates.
+    NotAnyOf(Vec<char>),
     Alt(Box<Re>, Box<Re>),
     Seq(Box<Re>, Box<Re>),
+    And(Box<Re>, Box<Re>),
     Star(Box<Re>),
+    Plus(Box<Re>),
+    Opt(Box<Re>),
+    Not(Box<Re>),
+    /// Exactly this many repetitions in a row. Built as a tree that shares one copy of the
+    /// repeated pattern, see `ImplicitRe::times`.
+    Repeat(Box<Re>, usize),
+    /// Matches the same as its child, tagged with an id, see `ImplicitRe::mark`.
+    Mark(u32, Box<Re>),
 }
 
 impl Re {
     pub fn char(c: char) -> Self {
         Self::Char(c)
     }
+
+    /// Any one `char` in `lo..=hi`. Panics if `lo > hi`.
+    pub fn range(lo: char, hi: char) -> Self {
+        assert!(lo <= hi, "invalid range {:?}-{:?}", lo, hi);
+        Self::Range(lo, hi)
+    }
+
+    /// Any one `char`, like `.` (but including `'\n'`).
+    pub fn any() -> Self {
+        Self::Any
+    }
+
+    /// Any one of `chars`, like `[aeiou]`. Unlike an alternation of every `char`, this is built
+    /// into a single node, that derives in one binary search.
+    pub fn one_of(chars: impl IntoIterator<Item = char>) -> Self {
+        Self::AnyOf(sorted_set(chars))
+    }
+
+    /// Any one `char` that isn't in `chars`, like `[^0-9]`. Like `any`, this includes `'\n'`.
+    pub fn none_of(chars: impl IntoIterator<Item = char>) -> Self {
+        Self::NotAnyOf(sorted_set(chars))
+    }
+
+    /// Builds this plan into a `Regex`, the same as `Regex::from(&plan)`, but at the end of a chain
+    /// of combinators.
+    ///
+    /// ```
+    /// use fast_regex::regex::build_plan::ImplicitRe;
+    ///
+    /// assert!("ab".re().star().build().is_match("abab"));
+    /// ```
+    pub fn build(&self) -> Regex<'static> {
+        Regex::from(self)
+    }
+
+    /// How many nodes this plan is made of. A set like `one_of("aeiou")` is one node.
+    pub fn node_count(&self) -> usize {
+        let mut count = 0;
+        let mut stack = vec![self];
+        while let Some(r) = stack.pop() {
+            count += 1;
+            stack.extend(r.children());
+        }
+        count
+    }
+
+    /// How many nodes deep this plan is, e.g. 1 for a single `char` and 2 for `a*`.
+    pub fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((r, d)) = stack.pop() {
+            depth = depth.max(d);
+            stack.extend(r.children().map(|c| (c, d + 1)));
+        }
+        depth
+    }
+
+    pub(super) fn children(&self) -> impl Iterator<Item = &Re> {
+        let (r1, r2) = match self {
+            Re::Alt(r1, r2) | Re::Seq(r1, r2) | Re::And(r1, r2) => (Some(r1), Some(r2)),
+            Re::Star(r)
+            | Re::Plus(r)
+            | Re::Opt(r)
+            | Re::Not(r)
+            | Re::Repeat(r, _)
+            | Re::Mark(_, r) => (Some(r), None),
+            _ => (None, None),
+        };
+        r1.into_iter().chain(r2).map(Box::as_ref)
+    }
+
+    /// Moves every child that has children of its own onto `stack`, leaving `One` in its place.
+    fn take_children(&mut self, stack: &mut Vec<Re>) {
+        let mut take = |r: &mut Box<Re>| {
+            if let Re::Alt(..)
+            | Re::Seq(..)
+            | Re::And(..)
+            | Re::Star(_)
+            | Re::Plus(_)
+            | Re::Opt(_)
+            | Re::Not(_)
+            | Re::Repeat(..)
+            | Re::Mark(..) = r.as_ref()
+            {
+                stack.push(std::mem::replace(r.as_mut(), Re::One));
+            }
+        };
+        match self {
+            Re::Alt(r1, r2) | Re::Seq(r1, r2) | Re::And(r1, r2) => {
+                take(r1);
+                take(r2);
+            }
+            Re::Star(r)
+            | Re::Plus(r)
+            | Re::Opt(r)
+            | Re::Not(r)
+            | Re::Repeat(r, _)
+            | Re::Mark(_, r) => take(r),
+            Re::One
+            | Re::Zero
+            | Re::Char(_)
+            | Re::Range(..)
+            | Re::Any
+            | Re::AnyOf(_)
+            | Re::NotAnyOf(_) => {}
+        }
+    }
+
+    /// Rebuilds the plan from the leaves up, replacing every node with `f` of it. `f` is only
+    /// called on a node once its children have been replaced.
+    ///
+    /// Like `Drop`, this uses an explicit stack, so it works on very deep plans.
+    pub fn rewrite(self, mut f: impl FnMut(Re) -> Re) -> Re {
+        enum Frame {
+            /// Rewrite the children of this node.
+            Enter(Re),
+            /// The rewritten children of this node are on top of the `done` stack.
+            Exit(Re),
+        }
+
+        let mut stack = vec![Frame::Enter(self)];
+        let mut done = Vec::new();
+        while let Some(frame) = stack.pop() {
+            match frame {
+                Frame::Enter(mut r) => match &mut r {
+                    Re::Alt(r1, r2) | Re::Seq(r1, r2) | Re::And(r1, r2) => {
+                        let (r1, r2) = (take(r1), take(r2));
+                        stack.push(Frame::Exit(r));
+                        stack.push(Frame::Enter(r2));
+                        stack.push(Frame::Enter(r1));
+                    }
+                    Re::Star(r1)
+                    | Re::Plus(r1)
+                    | Re::Opt(r1)
+                    | Re::Not(r1)
+                    | Re::Repeat(r1, _)
+                    | Re::Mark(_, r1) => {
+                        let r1 = take(r1);
+                        stack.push(Frame::Exit(r));
+                        stack.push(Frame::Enter(r1));
+                    }
+                    Re::One
+                    | Re::Zero
+                    | Re::Char(_)
+                    | Re::Range(..)
+                    | Re::Any
+                    | Re::AnyOf(_)
+                    | Re::NotAnyOf(_) => done.push(f(r)),
+                },
+                Frame::Exit(mut r) => {
+                    match &mut r {
+                        Re::Alt(r1, r2) | Re::Seq(r1, r2) | Re::And(r1, r2) => {
+                            **r2 = done.pop().unwrap();
+                            **r1 = done.pop().unwrap();
+                        }
+                        Re::Star(r1)
+                        | Re::Plus(r1)
+                        | Re::Opt(r1)
+                        | Re::Not(r1)
+                        | Re::Repeat(r1, _)
+                        | Re::Mark(_, r1) => **r1 = done.pop().unwrap(),
+                        Re::One
+                        | Re::Zero
+                        | Re::Char(_)
+                        | Re::Range(..)
+                        | Re::Any
+                        | Re::AnyOf(_)
+                        | Re::NotAnyOf(_) => {
+                            unreachable!("leaves are done on entry")
+                        }
+                    }
+                    done.push(f(r));
+                }
+            }
+        }
+        done.pop().unwrap()
+    }
+
+    /// Replaces every `char` of every `Char`, `AnyOf` and `NotAnyOf` with `f` of it. A `Range`
+    /// doesn't have its `char`s spelled out, so it is left alone.
+    ///
+    /// ```
+    /// use fast_regex::regex::build_plan::ImplicitRe;
+    ///
+    /// let plan = "ab".re().star().map_chars(|c| c.to_ascii_uppercase());
+    /// assert_eq!(plan, "AB".re().star());
+    /// ```
+    pub fn map_chars(self, mut f: impl FnMut(char) -> char) -> Re {
+        self.rewrite(|r| match r {
+            Re::Char(c) => Re::Char(f(c)),
+            Re::AnyOf(ref chars) => Re::one_of(chars.iter().copied().map(&mut f)),
+            Re::NotAnyOf(ref chars) => Re::none_of(chars.iter().copied().map(&mut f)),
+            r => r,
+        })
+    }
+
+    /// Folds the plan from the leaves up: `f` is called on every node, along with what it gave for
+    /// each of that node's children, in order. Like `rewrite`, this works on very deep plans.
+    ///
+    /// ```
+    /// use fast_regex::regex::build_plan::{ImplicitRe, Re};
+    ///
+    /// let chars = |r: &Re, children: Vec<usize>| {
+    ///     children.into_iter().sum::<usize>() + matches!(r, Re::Char(_)) as usize
+    /// };
+    /// assert_eq!("ab".alt('c'.star()).fold(chars), 3);
+    /// ```
+    pub fn fold<T>(&self, mut f: impl FnMut(&Re, Vec<T>) -> T) -> T {
+        enum Frame<'p> {
+            /// Fold the children of this node.
+            Enter(&'p Re),
+            /// What this node's children folded to is on top of the `done` stack.
+            Exit(&'p Re, usize),
+        }
+
+        let mut stack = vec![Frame::Enter(self)];
+        let mut done = Vec::new();
+        while let Some(frame) = stack.pop() {
+            match frame {
+                Frame::Enter(r) => {
+                    let children: Vec<_> = r.children().collect();
+                    stack.push(Frame::Exit(r, children.len()));
+                    stack.extend(children.into_iter().rev().map(Frame::Enter));
+                }
+                Frame::Exit(r, n) => {
+                    let children = done.split_off(done.len() - n);
+                    done.push(f(r, children));
+                }
+            }
+        }
+        done.pop().unwrap()
+    }
+}
+
+/// `chars`, sorted and without duplicates, for `AnyOf` and `NotAnyOf`.
+fn sorted_set(chars: impl IntoIterator<Item = char>) -> Vec<char> {
+    let mut chars: Vec<char> = chars.into_iter().collect();
+    chars.sort_unstable();
+    chars.dedup();
+    chars
+}
+
+/// Moves `r` out of its box, leaving `One` in its place. `Re` implements `Drop`, so its children
+/// can't be moved out of it directly.
+pub(super) fn take(r: &mut Box<Re>) -> Re {
+    std::mem::replace(r.as_mut(), Re::One)
+}
+
+/// How tightly each node binds when printed, loosest first. A child that binds more loosely than
+/// its parent needs parentheses.
+const ALT: u8 = 0;
+const AND: u8 = 1;
+const SEQ: u8 = 2;
+const NOT: u8 = 3;
+const ATOM: u8 = 4;
+
+impl Re {
+    fn precedence(&self) -> u8 {
+        match self {
+            Re::Alt(..) => ALT,
+            Re::And(..) => AND,
+            Re::Seq(..) => SEQ,
+            Re::Not(_) => NOT,
+            _ => ATOM,
+        }
+    }
+}
+
+/// Writes `c` so that `parse` reads it back as a literal.
+fn write_literal(f: &mut fmt::Formatter<'_>, c: char) -> fmt::Result {
+    if "\\|*+?.()[]!&".contains(c) {
+        write!(f, "\\")?;
+    }
+    write!(f, "{}", c)
+}
+
+/// Prints the plan in the syntax `parse` understands, e.g. `a(b|c)*`. `Zero`, `Range`, `AnyOf`,
+/// `NotAnyOf`, `And`, `Not`, `Repeat` and `Mark` have no syntax there, so they are printed as
+/// `[]`, `[a-z]`, `[aeiou]`, `[^aeiou]`, `a&b`, `!a`, `a{3}` and `<1:a>`.
+///
+/// Like `Drop`, this uses an explicit stack, so it works on very deep plans.
+impl fmt::Display for Re {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        enum Item<'r> {
+            /// A node that has to bind at least this tightly, or be parenthesized.
+            Node(&'r Re, u8),
+            Text(&'static str),
+            Count(usize),
+            Mark(u32),
+        }
+
+        let mut stack = vec![Item::Node(self, ALT)];
+        while let Some(item) = stack.pop() {
+            let (r, min) = match item {
+                Item::Text(text) => {
+                    write!(f, "{}", text)?;
+                    continue;
+                }
+                Item::Count(n) => {
+                    write!(f, "{{{}}}", n)?;
+                    continue;
+                }
+                Item::Mark(id) => {
+                    write!(f, "<{}:", id)?;
+                    continue;
+                }
+                Item::Node(r, min) => (r, min),
+            };
+            if r.precedence() < min {
+                stack.push(Item::Text(")"));
+                stack.push(Item::Node(r, ALT));
+                stack.push(Item::Text("("));
+                continue;
+            }
+            // children are pushed in reverse, so that they are popped in order
+            match r {
+                // the empty string has to be spelled out when something is applied to it
+                Re::One if min == ATOM => write!(f, "()")?,
+                Re::One => {}
+                Re::Zero => write!(f, "[]")?,
+                Re::Char(c) => write_literal(f, *c)?,
+                Re::Range(lo, hi) => write!(f, "[{}-{}]", lo, hi)?,
+                Re::Any => write!(f, ".")?,
+                Re::AnyOf(chars) => write!(f, "[{}]", chars.iter().collect::<String>())?,
+                Re::NotAnyOf(chars) => write!(f, "[^{}]", chars.iter().collect::<String>())?,
+                Re::Alt(r1, r2) => {
+                    stack.push(Item::Node(r2, ALT));
+                    stack.push(Item::Text("|"));
+                    stack.push(Item::Node(r1, ALT));
+                }
+                Re::And(r1, r2) => {
+                    stack.push(Item::Node(r2, AND));
+                    stack.push(Item::Text("&"));
+                    stack.push(Item::Node(r1, AND));
+                }
+                Re::Seq(r1, r2) => {
+                    stack.push(Item::Node(r2, SEQ));
+                    stack.push(Item::Node(r1, SEQ));
+                }
+                Re::Star(r1) | Re::Plus(r1) | Re::Opt(r1) => {
+                    stack.push(Item::Text(match r {
+                        Re::Star(_) => "*",
+                        Re::Plus(_) => "+",
+                        _ => "?",
+                    }));
+                    stack.push(Item::Node(r1, ATOM));
+                }
+                Re::Not(r1) => {
+                    stack.push(Item::Node(r1, NOT));
+                    stack.push(Item::Text("!"));
+                }
+                Re::Repeat(r1, n) => {
+                    stack.push(Item::Count(*n));
+                    stack.push(Item::Node(r1, ATOM));
+                }
+                Re::Mark(id, r1) => {
+                    stack.push(Item::Text(">"));
+                    stack.push(Item::Node(r1, ALT));
+                    stack.push(Item::Mark(*id));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The derived drop glue recurses once per node, which overflows the stack for very deep plans.
+impl Drop for Re {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.take_children(&mut stack);
+        while let Some(mut r) = stack.pop() {
+            r.take_children(&mut stack);
+        }
+    }
 }
 
 pub trait ImplicitRe: Into<Re> {
@@ -31,24 +432,117 @@ pub trait ImplicitRe: Into<Re> {
         Re::Seq(self.into_boxed(), rhs.into_boxed())
     }
 
+    /// Every string that both `self` and `rhs` match.
+    fn and(self, rhs: impl ImplicitRe) -> Re {
+        Re::And(self.into_boxed(), rhs.into_boxed())
+    }
+
     fn star(self) -> Re {
         Re::Star(self.into_boxed())
     }
+
+    /// One or more repetitions, like `r.seq(r.star())` but in a single node.
+    fn plus(self) -> Re {
+        Re::Plus(self.into_boxed())
+    }
+
+    /// Zero or one repetitions, like `r.alt("")` but in a single node.
+    fn opt(self) -> Re {
+        Re::Opt(self.into_boxed())
+    }
+
+    /// Every string that `self` doesn't match.
+    fn complement(self) -> Re {
+        Re::Not(self.into_boxed())
+    }
+
+    /// Tags `self` with `id`, without changing what it matches, so that `Regex::captures` can
+    /// report where it started.
+    fn mark(self, id: u32) -> Re {
+        Re::Mark(id, self.into_boxed())
+    }
+
+    /// Matches regardless of case, by making every `Char` and `Range` match its other cases too.
+    /// Case mappings to more than one `char` are skipped, so `'ß'` doesn't match `"SS"`, see
+    /// `PlanTransform::case_fold`.
+    fn fold_case(self) -> Re {
+        self.re().transform().case_fold().into_plan()
+    }
+
+    /// Exactly `n` repetitions, like `r{n}`. `r{0}` is just `One`.
+    fn repeat_exact(self, n: usize) -> Re {
+        let r = self.re();
+        let mut acc = None;
+        for _ in 0..n {
+            acc = Some(seq_opt(acc, r.clone()));
+        }
+        acc.unwrap_or(Re::One)
+    }
+
+    /// Exactly `n` repetitions, like `repeat_exact`, but as a single `Repeat` node.
+    ///
+    /// `repeat_exact` builds `n` copies of `self`, while this is built into a `Seq` of `Seq`s
+    /// that share one copy, so it takes O(log n) nodes. Derivatives still unroll it as they go,
+    /// so this only saves on the built `Regex`, not on matching.
+    fn times(self, n: usize) -> Re {
+        Re::Repeat(self.into_boxed(), n)
+    }
+
+    /// At least `n` repetitions, like `r{n,}`. `r{0,}` is just `r*`.
+    fn repeat_at_least(self, n: usize) -> Re {
+        let r = self.re();
+        let star = r.clone().star();
+        match n {
+            0 => star,
+            n => r.repeat_exact(n).seq(star),
+        }
+    }
+
+    /// Between `n` and `m` repetitions, like `r{n,m}`. Matches nothing if `n > m`.
+    ///
+    /// The optional repetitions are nested, like `rr(rr?)?` for `r{2,4}`, so that there is only
+    /// ever one way to match each of them.
+    fn repeat_range(self, n: usize, m: usize) -> Re {
+        if n > m {
+            return Re::Zero;
+        }
+        let r = self.re();
+        let mut tail: Option<Re> = None;
+        for _ in n..m {
+            tail = Some(match tail {
+                None => r.clone().opt(),
+                Some(tail) => r.clone().seq(tail).opt(),
+            });
+        }
+        match tail {
+            None => r.repeat_exact(n),
+            Some(tail) if n == 0 => tail,
+            Some(tail) => r.repeat_exact(n).seq(tail),
+        }
+    }
 }
 
-impl Into<Re> for char {
-    fn into(self) -> Re {
-        Re::Char(self)
+/// `acc` followed by `r`, or just `r` if there is nothing before it.
+fn seq_opt(acc: Option<Re>, r: Re) -> Re {
+    match acc {
+        Some(acc) => acc.seq(r),
+        None => r,
     }
 }
 
-impl<'a> Into<Re> for &'a str {
-    fn into(self) -> Re {
-        if self.len() == 0 {
+impl From<char> for Re {
+    fn from(value: char) -> Self {
+        Re::Char(value)
+    }
+}
+
+impl From<&str> for Re {
+    fn from(value: &str) -> Self {
+        if value.is_empty() {
             return Re::One;
         }
 
-        let mut iter = self.chars();
+        let mut iter = value.chars();
         let mut r: Re = iter.next().unwrap().into();
         for c in iter {
             r = r.seq(c)
@@ -60,4 +554,75 @@ impl<'a> Into<Re> for &'a str {
 
 impl ImplicitRe for Re {}
 impl ImplicitRe for char {}
-impl<'a> ImplicitRe for &'a str {}
+impl ImplicitRe for &str {}
+
+/// Alternation, like `ImplicitRe::alt`. `+` binds more tightly than `|`, just like sequencing does
+/// in a pattern, so `a + b | c` is `ab|c`.
+///
+/// ```
+/// use fast_regex::regex::{build_plan::ImplicitRe, Regex};
+///
+/// let r = Regex::from(&(('a'.re() + "bc") | 'd'));
+/// assert!(r.is_match("abc"));
+/// assert!(r.is_match("d"));
+/// assert!(!r.is_match("abd"));
+/// ```
+impl<R: ImplicitRe> ops::BitOr<R> for Re {
+    type Output = Re;
+
+    fn bitor(self, rhs: R) -> Re {
+        self.alt(rhs)
+    }
+}
+
+/// Sequencing, like `ImplicitRe::seq`. There is no operator for repetition, see `star`.
+///
+/// ```
+/// use fast_regex::regex::{build_plan::ImplicitRe, Regex};
+///
+/// let r = Regex::from(&("ab".re() + 'c'.star() + "d"));
+/// assert!(r.is_match("abcccd"));
+/// assert!(!r.is_match("abc"));
+/// ```
+impl<R: ImplicitRe> ops::Add<R> for Re {
+    type Output = Re;
+
+    fn add(self, rhs: R) -> Re {
+        self.seq(rhs)
+    }
+}
+
+// So that a `char` or `&str` can be on the left as long as a `Re` is on the right, e.g.
+// `'a' | 'b'.star()`. Two of them still need a `.re()` first.
+
+impl ops::BitOr<Re> for char {
+    type Output = Re;
+
+    fn bitor(self, rhs: Re) -> Re {
+        self.alt(rhs)
+    }
+}
+
+impl ops::Add<Re> for char {
+    type Output = Re;
+
+    fn add(self, rhs: Re) -> Re {
+        self.seq(rhs)
+    }
+}
+
+impl ops::BitOr<Re> for &str {
+    type Output = Re;
+
+    fn bitor(self, rhs: Re) -> Re {
+        self.alt(rhs)
+    }
+}
+
+impl ops::Add<Re> for &str {
+    type Output = Re;
+
+    fn add(self, rhs: Re) -> Re {
+        self.seq(rhs)
+    }
+}
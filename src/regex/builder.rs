@@ -0,0 +1,111 @@
+use std::marker::PhantomData;
+
+use super::*;
+
+#[derive(Clone, Copy)]
+enum Op {
+    Seq,
+    Alt,
+}
+
+/// Composes several already-built `Regex`es into one. Composing with `seq`/`alt` only records the
+/// trees, and `build` then copies all of them into a single allocator of exactly the right size,
+/// so there are no intermediate copies.
+pub struct RegexBuilder<'r> {
+    first: Const<Re>,
+    rest: Vec<(Op, Const<Re>)>,
+    /// See `hash_consing`.
+    hash_consing: bool,
+    phantom: PhantomData<&'r ()>,
+}
+
+impl<'r> RegexBuilder<'r> {
+    pub fn new(first: &'r Regex) -> Self {
+        Self {
+            first: first.tree,
+            rest: Vec::new(),
+            hash_consing: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sequences everything composed so far with `r`.
+    pub fn seq(mut self, r: &'r Regex) -> Self {
+        self.rest.push((Op::Seq, r.tree));
+        self
+    }
+
+    /// Alternates everything composed so far with `r`.
+    pub fn alt(mut self, r: &'r Regex) -> Self {
+        self.rest.push((Op::Alt, r.tree));
+        self
+    }
+
+    /// Makes `build` keep only one copy of every distinct subtree, see `Regex::compact`. The
+    /// composed trees often have parts in common, e.g. alternatives that end the same way. This
+    /// takes a hash map as big as the result while building, so it is off by default.
+    pub fn hash_consing(mut self, hash_consing: bool) -> Self {
+        self.hash_consing = hash_consing;
+        self
+    }
+
+    /// ## Safety
+    /// `alloc` must not own any of the trees, and they must all be valid for reads.
+    unsafe fn build_rec(&self, alloc: &mut VecAlloc<Re>) -> Result<Const<Re>, ()> {
+        let mut tree = Regex::rebuild_rec(alloc, self.first)?;
+        for &(op, r) in &self.rest {
+            let r = Regex::rebuild_rec(alloc, r)?;
+            tree = try_alloc(
+                alloc,
+                match op {
+                    Op::Seq => Re::Seq(tree, r),
+                    Op::Alt => Re::Alt(tree, r),
+                },
+            )?;
+        }
+        Ok(tree)
+    }
+
+    /// Builds the composed `Regex`, which owns all of its nodes.
+    pub fn build(&self) -> Regex<'static> {
+        let roots = std::iter::once(self.first).chain(self.rest.iter().map(|(_, r)| *r));
+        // one node for every node in every tree, plus one `Seq`/`Alt` to join each of them on
+        let capacity = roots.map(|r| tree_size(r) + 1).sum::<usize>() - 1;
+        let mut alloc = VecAlloc::new(capacity);
+        let tree = loop {
+            // SAFETY: the trees are borrowed for `'r`, and `alloc` is brand new
+            match unsafe { self.build_rec(&mut alloc) } {
+                Ok(tree) => break tree,
+                Err(_) => alloc.resize(),
+            }
+        };
+        // SAFETY: `tree` is owned by `alloc`
+        let regex = unsafe { Regex::new(tree, alloc) };
+        if self.hash_consing {
+            regex.compact()
+        } else {
+            regex
+        }
+    }
+}
+
+/// How many nodes copying `r` takes. Shared subtrees count once for every time they are shared.
+fn tree_size(r: Const<Re>) -> usize {
+    let mut size = 0;
+    let mut stack = vec![r];
+    while let Some(r) = stack.pop() {
+        size += 1;
+        // SAFETY: only called on trees borrowed by a `RegexBuilder`
+        match unsafe { r.read() } {
+            Re::Zero | Re::One | Re::Char(_) | Re::Range(..) | Re::Any => {}
+            // the set is copied along with it
+            Re::AnyOf(_, len) | Re::NotAnyOf(_, len) => size += len,
+            Re::Alt(r1, r2) | Re::Seq(r1, r2) | Re::And(r1, r2) => {
+                stack.push(r1);
+                stack.push(r2);
+            }
+            Re::Star(r) | Re::Plus(r) | Re::Opt(r) | Re::Not(r) | Re::Mark(_, r) => stack.push(r),
+        }
+    }
+    size
+}
@@ -0,0 +1,35 @@
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
+
+use super::parse::{parse_with_boundaries, ParseError};
+use super::Regex;
+
+/// A `Regex` that owns every one of its nodes.
+pub type CompiledRegex = Regex<'static>;
+
+thread_local! {
+    static CACHE: RefCell<HashMap<String, Arc<CompiledRegex>>> = RefCell::new(HashMap::new());
+}
+
+impl Regex<'static> {
+    /// Parses and builds `pattern`, unless it has already been built on this thread, in which case
+    /// the same `Regex` is handed out again.
+    pub fn get_or_compile(pattern: &str) -> Result<Arc<CompiledRegex>, ParseError> {
+        CACHE.with(|cache| {
+            if let Some(r) = cache.borrow().get(pattern) {
+                return Ok(Arc::clone(r));
+            }
+            let (plan, boundaries) = parse_with_boundaries(pattern)?;
+            let r = Arc::new(Regex::from(&plan).with_boundaries(boundaries));
+            cache
+                .borrow_mut()
+                .insert(pattern.to_string(), Arc::clone(&r));
+            Ok(r)
+        })
+    }
+
+    /// Empties this thread's cache of compiled patterns. `Arc`s that have already been handed out
+    /// stay valid.
+    pub fn clear_cache() {
+        CACHE.with(|cache| cache.borrow_mut().clear());
+    }
+}
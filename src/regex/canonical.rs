@@ -0,0 +1,133 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+use super::*;
+
+/// A state of a minimized DFA: whether it accepts, and where each range of `char`s leads.
+type State = (bool, Vec<(CharRange, usize)>);
+
+/// A `Regex` that hashes and compares by the language it accepts, instead of by its structure. Two
+/// patterns that accept exactly the same strings, like `a|aa` and `a(a|)`, are equal, so this can
+/// be used as a `HashMap` key to deduplicate patterns.
+///
+/// Only the pattern itself is compared. Normalizers and boundaries are ignored.
+pub struct CanonicalRegex {
+    regex: Regex<'static>,
+    /// The minimal DFA, with states numbered in breadth-first order from the start state and
+    /// transitions sorted by range. Any two equivalent patterns end up with the same one.
+    dfa: Vec<State>,
+}
+
+impl CanonicalRegex {
+    pub fn regex(&self) -> &Regex<'static> {
+        &self.regex
+    }
+
+    pub fn into_regex(self) -> Regex<'static> {
+        self.regex
+    }
+}
+
+impl PartialEq for CanonicalRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.dfa == other.dfa
+    }
+}
+
+impl Eq for CanonicalRegex {}
+
+impl Hash for CanonicalRegex {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.dfa.hash(state);
+    }
+}
+
+impl fmt::Debug for CanonicalRegex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Canonical{:?}", self.regex)
+    }
+}
+
+/// Merges equivalent states of `states` (Moore's algorithm), returning the block of every state.
+fn minimize(states: &[State]) -> Vec<usize> {
+    let mut blocks: Vec<usize> = states
+        .iter()
+        .map(|(accepts, _)| *accepts as usize)
+        .collect();
+    loop {
+        let mut numbering = HashMap::new();
+        let refined: Vec<usize> = states
+            .iter()
+            .enumerate()
+            .map(|(i, (_, transitions))| {
+                let signature: Vec<usize> = transitions.iter().map(|&(_, j)| blocks[j]).collect();
+                let next = numbering.len();
+                *numbering.entry((blocks[i], signature)).or_insert(next)
+            })
+            .collect();
+        // refinement only ever splits blocks, so the same count means nothing changed
+        let count = |b: &[usize]| b.iter().max().map_or(0, |m| m + 1);
+        if count(&refined) == count(&blocks) {
+            return refined;
+        }
+        blocks = refined;
+    }
+}
+
+impl<'a> Regex<'a> {
+    /// Wraps this `Regex` so that it hashes and compares by language, see `CanonicalRegex`.
+    ///
+    /// This explores every state of the DFA, so like `walk_states` it can fail to terminate for
+    /// patterns whose derivatives keep growing.
+    pub fn canonicalize(&self) -> CanonicalRegex {
+        let mut states: Vec<State> = Vec::new();
+        self.walk_states(|_, accepts, transitions| states.push((accepts, transitions.to_vec())));
+
+        let blocks = minimize(&states);
+        // one representative state for each block
+        let mut merged: HashMap<usize, State> = HashMap::new();
+        for (i, (accepts, transitions)) in states.iter().enumerate() {
+            merged.entry(blocks[i]).or_insert_with(|| {
+                // join neighbouring ranges that now lead to the same block
+                let mut joined: Vec<(CharRange, usize)> = Vec::new();
+                for &(range, j) in transitions {
+                    match joined.last_mut() {
+                        Some((last, target)) if *target == blocks[j] => {
+                            *last = CharRange::new(last.start, range.end)
+                        }
+                        _ => joined.push((range, blocks[j])),
+                    }
+                }
+                (*accepts, joined)
+            });
+        }
+
+        // renumber breadth-first from the start state, so the numbering doesn't depend on how
+        // the pattern was written
+        let mut order = HashMap::from([(blocks[0], 0)]);
+        let mut worklist = VecDeque::from([blocks[0]]);
+        let mut dfa = Vec::with_capacity(merged.len());
+        while let Some(block) = worklist.pop_front() {
+            let (accepts, transitions) = &merged[&block];
+            let transitions = transitions
+                .iter()
+                .map(|&(range, target)| {
+                    let next = order.len();
+                    let j = *order.entry(target).or_insert_with(|| {
+                        worklist.push_back(target);
+                        next
+                    });
+                    (range, j)
+                })
+                .collect();
+            dfa.push((*accepts, transitions));
+        }
+
+        CanonicalRegex {
+            regex: self.clone(),
+            dfa,
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use super::*;
+
+impl<'a> Regex<'a> {
+    /// Where each `Mark` (see `ImplicitRe::mark`) started, by its id, as a byte offset into `s`.
+    /// Returns `None` if `s` doesn't match, and leaves out marks that the match doesn't go through,
+    /// like the unused side of an `Alt` or anything under a complement.
+    ///
+    /// Derivatives drop marks, so this matches `s` first and then splits it up over the original
+    /// tree: each `Seq` and each repetition takes the longest first part that still lets the rest
+    /// match. A mark that is entered more than once, e.g. in a `Star`, reports where it was first
+    /// entered. Each split derives over the input again, so this is O(n²) for an input of length
+    /// n, on top of what `is_match` takes.
+    ///
+    /// ```
+    /// use fast_regex::regex::{build_plan::ImplicitRe, Regex};
+    ///
+    /// let r = Regex::from(&'a'.star().mark(1).seq('b'.mark(2)));
+    /// let captures = r.captures("aab").unwrap();
+    /// assert_eq!(captures[&1], 0);
+    /// assert_eq!(captures[&2], 2);
+    /// assert!(r.captures("aa").is_none());
+    /// ```
+    pub fn captures(&self, s: &str) -> Option<HashMap<u32, usize>> {
+        if !self.is_match(s) {
+            return None;
+        }
+        let mut marks = HashMap::new();
+        // (node, where its match starts, where it ends), for nodes that are known to match there
+        let mut stack = vec![(self.tree, 0, s.len())];
+        while let Some((r, start, end)) = stack.pop() {
+            let matches = |r, start, end| self.subtree(r).is_full_match(&s[start..end]);
+            // SAFETY: every node is owned by this `Regex` or one of its parents
+            match unsafe { r.read() } {
+                Re::Mark(id, r1) => {
+                    marks.entry(id).or_insert(start);
+                    stack.push((r1, start, end));
+                }
+                Re::Alt(r1, r2) => match matches(r1, start, end) {
+                    true => stack.push((r1, start, end)),
+                    false => stack.push((r2, start, end)),
+                },
+                Re::And(r1, r2) => {
+                    stack.push((r2, start, end));
+                    stack.push((r1, start, end));
+                }
+                Re::Seq(r1, r2) => {
+                    let mid = (self.subtree(r1))
+                        .prefix_ends(s, start, end)
+                        .into_iter()
+                        .rev()
+                        .find(|&mid| matches(r2, mid, end))
+                        .expect("one of the splits matches");
+                    // pushed second, so that marks on the left are entered first
+                    stack.push((r2, mid, end));
+                    stack.push((r1, start, mid));
+                }
+                Re::Opt(r1) => {
+                    if matches(r1, start, end) {
+                        stack.push((r1, start, end));
+                    }
+                }
+                // the empty string needs no repetitions, except the one a `Plus` has to make
+                Re::Star(_) if start == end => {}
+                Re::Plus(r1) if start == end => stack.push((r1, start, end)),
+                Re::Star(r1) | Re::Plus(r1) => {
+                    // every repetition after the first one is non-empty, so the first one can be
+                    let mid = (self.subtree(r1))
+                        .prefix_ends(s, start, end)
+                        .into_iter()
+                        .rev()
+                        .find(|&mid| mid > start && (mid == end || matches(r, mid, end)))
+                        .expect("one of the splits matches");
+                    if mid < end {
+                        stack.push((r, mid, end));
+                    }
+                    stack.push((r1, start, mid));
+                }
+                // a match of a complement doesn't go through any of its nodes
+                Re::Not(_) => {}
+                Re::Zero
+                | Re::One
+                | Re::Char(_)
+                | Re::Range(..)
+                | Re::Any
+                | Re::AnyOf(..)
+                | Re::NotAnyOf(..) => {}
+            }
+        }
+        Some(marks)
+    }
+
+    /// A `Regex` for the subtree `r`, which has to be owned by this `Regex` or its parents.
+    fn subtree(&self, r: Const<Re>) -> Regex<'_> {
+        Regex {
+            tree: r,
+            alloc: VecAlloc::new(0),
+            normalizer: self.normalizer.clone(),
+            analyses: OnceLock::new(),
+            boundaries: Boundaries::default(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Every byte offset in `start..=end` that a match of this `Regex` starting at `start` can end
+    /// at, in order.
+    fn prefix_ends(&self, s: &str, start: usize, end: usize) -> Vec<usize> {
+        let mut ends = Vec::new();
+        if self.nullable() {
+            ends.push(start);
+        }
+        let mut r = self.clone();
+        for (i, c) in s[start..end].char_indices() {
+            r = r.der(self.normalize(c)).simp().clone();
+            // SAFETY: dereferencing a reference to immutable buffers
+            if let Re::Zero = unsafe { r.tree.as_ref() } {
+                break;
+            }
+            if r.nullable() {
+                ends.push(start + i + c.len_utf8());
+            }
+        }
+        ends
+    }
+}
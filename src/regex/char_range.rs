@@ -0,0 +1,85 @@
+use std::fmt;
+
+/// An inclusive range of `char`s. Ranges that cross the surrogate gap are fine, the surrogates are
+/// just never 'in' the range, since they aren't `char`s.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CharRange {
+    pub start: char,
+    pub end: char,
+}
+
+impl fmt::Debug for CharRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{:?}", self.start)
+        } else {
+            write!(f, "{:?}-{:?}", self.start, self.end)
+        }
+    }
+}
+
+/// The `char` after `c`, skipping over the surrogate gap.
+fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        c => char::from_u32(c as u32 + 1),
+    }
+}
+
+impl CharRange {
+    /// Panics if `start > end`.
+    pub fn new(start: char, end: char) -> Self {
+        assert!(start <= end, "invalid CharRange {:?}-{:?}", start, end);
+        Self { start, end }
+    }
+
+    pub fn single(c: char) -> Self {
+        Self::new(c, c)
+    }
+
+    /// The range containing every `char`.
+    pub fn all() -> Self {
+        Self::new('\0', char::MAX)
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.start <= c && c <= self.end
+    }
+
+    /// Any one `char` in the range. This is always a valid `char`, so it never lands inside the
+    /// surrogate gap.
+    pub fn representative(&self) -> char {
+        self.start
+    }
+
+    /// Splits every `char` into ranges, such that each of `points` gets a range to itself and the
+    /// `char`s in between are grouped together. The result is sorted.
+    pub fn partition(points: impl IntoIterator<Item = char>) -> Vec<CharRange> {
+        let mut points: Vec<char> = points.into_iter().collect();
+        points.sort_unstable();
+        points.dedup();
+
+        let mut ranges = Vec::with_capacity(points.len() * 2 + 1);
+        // `None` means we've already gone past `char::MAX`
+        let mut start = Some('\0');
+        for p in points {
+            // `start` can only be `None` after `char::MAX`, and there are no points after that
+            let s = start.unwrap();
+            if s < p {
+                // the `char` before `p`, skipping over the surrogate gap
+                let end = match p {
+                    '\u{E000}' => '\u{D7FF}',
+                    p => char::from_u32(p as u32 - 1).unwrap(),
+                };
+                ranges.push(CharRange::new(s, end));
+            }
+            ranges.push(CharRange::single(p));
+            start = next_char(p);
+        }
+        if let Some(s) = start {
+            ranges.push(CharRange::new(s, char::MAX));
+        }
+
+        ranges
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use super::*;
+
+/// What tells a node apart from every other one, once its children are shared: its kind, the id
+/// of a `Mark`, its classes (for `Char`, `Range` and sets) and the children it points at. Two
+/// nodes with the same key match the same thing, so only one of them has to be kept.
+type Key<A> = (
+    u8,
+    u32,
+    Vec<<A as Alphabet>::Class>,
+    [Option<NonNull<Re<A>>>; 2],
+);
+
+/// ## Safety
+/// The set of an `AnyOf` or `NotAnyOf` must be valid for reads.
+unsafe fn key<A: Alphabet>(r: Re<A>) -> Key<A> {
+    let (id, classes, children) = match r {
+        Re::Zero | Re::One | Re::Any => (0, vec![], [None, None]),
+        Re::Char(c) => (0, vec![c], [None, None]),
+        Re::Range(lo, hi) => (0, vec![lo, hi], [None, None]),
+        // SAFETY: guaranteed by the caller
+        Re::AnyOf(set, len) | Re::NotAnyOf(set, len) => {
+            (0, unsafe { any_of(set, len) }.collect(), [None, None])
+        }
+        Re::Alt(r1, r2) | Re::Seq(r1, r2) | Re::And(r1, r2) => {
+            (0, vec![], [Some(r1.as_non_null()), Some(r2.as_non_null())])
+        }
+        Re::Star(r1) | Re::Plus(r1) | Re::Opt(r1) | Re::Not(r1) => {
+            (0, vec![], [Some(r1.as_non_null()), None])
+        }
+        Re::Mark(id, r1) => (id, vec![], [Some(r1.as_non_null()), None]),
+    };
+    (r.rank(), id, classes, children)
+}
+
+impl<'a, A: Alphabet> Regex<'a, A> {
+    /// Copies the tree into a new allocator, keeping only one copy of every distinct subtree
+    /// (hash-consing). `build` and `der` allocate the same `Char` or the same branch over and
+    /// over, and after this, equal subtrees are the same node, so the pointer check that
+    /// comparisons start with settles them straight away.
+    ///
+    /// This takes a hash map as big as the tree, so it is left to the caller, see
+    /// `RegexBuilder::hash_consing`.
+    pub fn compact(&self) -> Regex<'static, A> {
+        // every distinct node is kept once, and `node_count` counts every node reachable
+        let mut alloc = VecAlloc::new(self.node_count());
+        let tree = loop {
+            // SAFETY: the tree is owned by this `Regex` or one of its parents, and `alloc` is new
+            match unsafe { Self::copy_rec(&mut alloc, self.tree, true, |r| r) } {
+                Ok(tree) => break tree,
+                Err(_) => alloc.resize(),
+            }
+        };
+
+        Regex {
+            tree,
+            alloc,
+            normalizer: self.normalizer.clone(),
+            // the same language, so the same analyses
+            analyses: self.analyses.clone(),
+            boundaries: self.boundaries,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Copies `r` into `alloc`, replacing every node with `f` of it once its children have been
+    /// copied. A node that is shared in `r` is only copied once, and with `hash_cons`, so is every
+    /// node equal to one that has already been copied.
+    ///
+    /// ## Safety
+    /// `r` must be valid for reads, and not owned by `alloc`.
+    pub(super) unsafe fn copy_rec(
+        alloc: &mut VecAlloc<Re<A>>,
+        r: Const<Re<A>>,
+        hash_cons: bool,
+        f: impl Fn(Re<A>) -> Re<A>,
+    ) -> Result<Const<Re<A>>, ()> {
+        // where every node of the old tree ended up, and the node kept for every key
+        let mut copies: HashMap<NonNull<Re<A>>, Const<Re<A>>> = HashMap::new();
+        let mut kept: HashMap<Key<A>, Const<Re<A>>> = HashMap::new();
+        // a node is pushed a second time, as `true`, to be copied once its children are
+        let mut stack = vec![(r, false)];
+        while let Some((r, children_done)) = stack.pop() {
+            if copies.contains_key(&r.as_non_null()) {
+                continue;
+            }
+            // SAFETY: guaranteed by the caller
+            let node = unsafe { r.read() };
+            if !children_done {
+                stack.push((r, true));
+                match node {
+                    Re::Alt(r1, r2) | Re::Seq(r1, r2) | Re::And(r1, r2) => {
+                        stack.push((r2, false));
+                        stack.push((r1, false));
+                    }
+                    Re::Star(r1) | Re::Plus(r1) | Re::Opt(r1) | Re::Not(r1) | Re::Mark(_, r1) => {
+                        stack.push((r1, false))
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            let copy = |r: Const<Re<A>>| copies[&r.as_non_null()];
+            let node = f(match node {
+                Re::Alt(r1, r2) => Re::Alt(copy(r1), copy(r2)),
+                Re::Seq(r1, r2) => Re::Seq(copy(r1), copy(r2)),
+                Re::And(r1, r2) => Re::And(copy(r1), copy(r2)),
+                Re::Star(r1) => Re::Star(copy(r1)),
+                Re::Plus(r1) => Re::Plus(copy(r1)),
+                Re::Opt(r1) => Re::Opt(copy(r1)),
+                Re::Not(r1) => Re::Not(copy(r1)),
+                Re::Mark(id, r1) => Re::Mark(id, copy(r1)),
+                // sets are copied along with their node, below
+                leaf => leaf,
+            });
+            // SAFETY: the set of a set node is still the old one, which is valid for reads
+            let key = hash_cons.then(|| unsafe { key(node) });
+            let kept = match key.as_ref().and_then(|key| kept.get(key)) {
+                Some(&kept) => kept,
+                None => {
+                    let new = match node {
+                        // SAFETY: as above
+                        Re::AnyOf(set, len) => {
+                            Re::AnyOf(alloc_set(alloc, unsafe { any_of(set, len) })?, len)
+                        }
+                        Re::NotAnyOf(set, len) => {
+                            Re::NotAnyOf(alloc_set(alloc, unsafe { any_of(set, len) })?, len)
+                        }
+                        node => node,
+                    };
+                    let new = try_alloc(alloc, new)?;
+                    if let Some(key) = key {
+                        kept.insert(key, new);
+                    }
+                    new
+                }
+            };
+            copies.insert(r.as_non_null(), kept);
+        }
+        Ok(copies[&r.as_non_null()])
+    }
+}
@@ -1,6 +1,5 @@
 use std::ptr::NonNull;
 
-
 /// This class is only meant so that I can make sure that I am not using any mutating methods on
 /// the internal pointer. It still needs to be constructed from some kind of mutable pointer.
 #[derive(Debug)]
@@ -15,21 +14,50 @@ impl<T> Const<T> {
         Self(NonNull::dangling())
     }
 
+    /// ## Safety
+    /// The pointer must be valid for reads.
     pub unsafe fn read(&self) -> T {
         self.0.as_ptr().read()
     }
 
+    /// ## Safety
+    /// The pointer must be valid for reads for the lifetime of the returned reference.
     pub unsafe fn as_ref(&self) -> &T {
         self.0.as_ref()
     }
 
+    pub fn as_non_null(self) -> NonNull<T> {
+        self.0
+    }
+
+    /// A `Const` with the pointer that `f` makes from this one's, e.g. to rebase it into another
+    /// buffer.
+    pub fn map(self, f: impl FnOnce(NonNull<T>) -> NonNull<T>) -> Self {
+        Self(f(self.0))
+    }
+
+    /// Replaces the pointer. Only there so that the result reads as derived from `self`.
+    pub fn with_ptr(self, ptr: NonNull<T>) -> Self {
+        self.map(|_| ptr)
+    }
+
+    /// Moves the pointer by `count` `T`s, like `NonNull::offset`.
+    ///
+    /// ## Safety
+    /// The result must still point into the same allocation as `self` (or one past its end, in
+    /// which case it must not be read), e.g. the buffer of the `VecAlloc` that `self` came from.
+    pub unsafe fn offset(self, count: isize) -> Self {
+        self.map(|ptr| unsafe { ptr.offset(count) })
+    }
+
     /// Performs pointer equality on two `Const<T>`s. Always safe.
     pub fn ptr_eq(self, rhs: Self) -> bool {
         self.0 == rhs.0
     }
 
-    /// SAFETY: `self` and `rhs` will be dereferenced and read. Aliasing safety is probably not a
-    /// concern if you are exclusively using `Const`, but the pointers could be dangling.
+    /// ## Safety
+    /// `self` and `rhs` will be dereferenced and read. Aliasing safety is probably not a concern
+    /// if you are exclusively using `Const`, but the pointers could be dangling.
     pub unsafe fn eq(self, rhs: Self, inner_eq: impl Fn(&T, &T) -> bool) -> bool {
         self.ptr_eq(rhs) || unsafe { inner_eq(self.as_ref(), rhs.as_ref()) }
     }
@@ -37,7 +65,7 @@ impl<T> Const<T> {
 
 impl<T> Clone for Const<T> {
     fn clone(&self) -> Self {
-        Const(self.0)
+        *self
     }
 }
 
@@ -53,4 +81,33 @@ impl<T> From<&T> for Const<T> {
     fn from(value: &T) -> Self {
         Self::new(value.into())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vec_alloc::VecAlloc;
+
+    #[test]
+    fn offset() {
+        let mut alloc = VecAlloc::new(4);
+        let ptrs: Vec<_> = (0..4).map(|i| alloc.alloc(i).unwrap()).collect();
+        let first = Const::new(ptrs[0]);
+        unsafe {
+            for (i, &ptr) in ptrs.iter().enumerate() {
+                let moved = first.offset(i as isize);
+                assert!(moved.ptr_eq(ptr.into()));
+                assert_eq!(moved.read(), i);
+                assert!(moved.offset(-(i as isize)).ptr_eq(first));
+            }
+        }
+    }
+
+    #[test]
+    fn with_ptr() {
+        let (a, b) = (1, 2);
+        let c = Const::from(&a);
+        assert!(c.with_ptr(NonNull::from(&b)).ptr_eq(Const::from(&b)));
+        assert!(c.map(|ptr| ptr).ptr_eq(c));
+    }
+}
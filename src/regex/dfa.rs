@@ -0,0 +1,228 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use super::*;
+
+/// The `char`s a `Regex` tells apart, as found by `Regex::alphabet`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiteralAlphabet {
+    /// Every `char` that appears as a literal, including the ends of ranges and every member of a
+    /// set.
+    pub chars: BTreeSet<char>,
+    /// Whether an `Any`, a complement or a `NotAnyOf` can match `char`s that aren't listed, the
+    /// "other" class. Without one, nothing outside of `chars` and its ranges ever matches.
+    pub has_other: bool,
+}
+
+impl<'a> Regex<'a> {
+    /// Every `char` that appears as a literal in this `Regex`, including the ends of ranges and
+    /// every member of a set. These are the only `char`s it tells apart: every `char` that isn't
+    /// listed (and isn't inside a range) behaves the same as every other one. `has_other` says
+    /// whether an `Any` or a complement can match those, or nothing does. `derivative_classes`
+    /// gives a representative for them too.
+    ///
+    /// ```
+    /// use fast_regex::regex::Regex;
+    ///
+    /// let alphabet = Regex::from_pattern("ab|a.").unwrap().alphabet();
+    /// assert_eq!(alphabet.chars.into_iter().collect::<String>(), "ab");
+    /// assert!(alphabet.has_other);
+    /// assert!(!Regex::from_pattern("ab|a").unwrap().alphabet().has_other);
+    /// ```
+    pub fn alphabet(&self) -> LiteralAlphabet {
+        let mut alphabet = LiteralAlphabet::default();
+        let chars = &mut alphabet.chars;
+        let mut stack = vec![self.tree];
+        while let Some(r) = stack.pop() {
+            // SAFETY: every node is owned by this `Regex` or one of its parents
+            match unsafe { r.read() } {
+                Re::Zero | Re::One => {}
+                Re::Any => alphabet.has_other = true,
+                Re::Char(c) => {
+                    chars.insert(c);
+                }
+                // SAFETY: as above
+                Re::AnyOf(set, len) => chars.extend(unsafe { any_of(set, len) }),
+                Re::NotAnyOf(set, len) => {
+                    alphabet.has_other = true;
+                    // SAFETY: as above
+                    chars.extend(unsafe { any_of(set, len) })
+                }
+                // the `char`s just inside the range are enough to tell it apart from the rest
+                Re::Range(lo, hi) => {
+                    chars.insert(lo);
+                    chars.insert(hi);
+                }
+                Re::Alt(r1, r2) | Re::Seq(r1, r2) | Re::And(r1, r2) => {
+                    stack.push(r1);
+                    stack.push(r2);
+                }
+                Re::Not(r) => {
+                    alphabet.has_other = true;
+                    stack.push(r)
+                }
+                Re::Star(r) | Re::Plus(r) | Re::Opt(r) | Re::Mark(_, r) => stack.push(r),
+            }
+        }
+        alphabet
+    }
+
+    /// Partitions every `char` into ranges that this `Regex` can't tell apart. Deriving by any
+    /// `char` in a range gives the same result as deriving by any other `char` in it.
+    pub fn derivative_classes(&self) -> Vec<CharRange> {
+        CharRange::partition(self.alphabet().chars)
+    }
+
+    /// Derives by every `char` in `range` at once, and simplifies the result. Every `char` in
+    /// `range` must have the same derivative (as a language), so that we can just derive by any one
+    /// of them. This always holds for ranges within one of the `derivative_classes`.
+    pub fn derivative_by_class(&self, range: CharRange) -> Regex<'static> {
+        self.der(range.representative()).simp().clone()
+    }
+
+    /// Explores every state reachable from this `Regex` by repeated derivation, calling `visit`
+    /// once per state with whether the state is accepting and its transitions. Each transition is a
+    /// range of `char`s and the index of the state it leads to, where states are numbered in the
+    /// order they are visited, starting at `0` for `self`.
+    ///
+    /// States are only recognised as the same if they `simp` to the same tree, so this can fail to
+    /// terminate for patterns whose derivatives keep growing.
+    pub fn walk_states(&self, mut visit: impl FnMut(&Regex, bool, &[(CharRange, usize)])) {
+        let classes = self.derivative_classes();
+        let mut states = vec![self.clone()];
+        let mut worklist = VecDeque::from([0]);
+
+        while let Some(i) = worklist.pop_front() {
+            let mut transitions = Vec::with_capacity(classes.len());
+            for &class in &classes {
+                let d = states[i].derivative_by_class(class);
+                // SAFETY: both trees are owned by live `Regex`es
+                let j = match states
+                    .iter()
+                    .position(|s| unsafe { Re::const_eq(s.tree, d.tree) })
+                {
+                    Some(j) => j,
+                    None => {
+                        states.push(d);
+                        worklist.push_back(states.len() - 1);
+                        states.len() - 1
+                    }
+                };
+                transitions.push((class, j));
+            }
+
+            visit(&states[i], states[i].nullable(), &transitions);
+        }
+    }
+
+    /// Checks whether this `Regex` doesn't match any string at all, not even the empty one.
+    ///
+    /// `length_bounds` can usually tell straight away. When it can't (e.g. for a complement), no
+    /// reachable state may be accepting, so like `walk_states` this can then fail to terminate
+    /// for patterns whose derivatives keep growing.
+    pub fn matches_nothing(&self) -> bool {
+        if self.length_bounds().is_none() {
+            return true;
+        }
+        let mut nothing = true;
+        self.walk_states(|_, accepting, _| nothing &= !accepting);
+        nothing
+    }
+
+    /// Checks whether this `Regex` matches every string. Every reachable state has to be accepting,
+    /// so like `walk_states` this can fail to terminate for patterns whose derivatives keep
+    /// growing.
+    pub fn matches_everything(&self) -> bool {
+        let mut everything = true;
+        self.walk_states(|_, accepting, _| everything &= accepting);
+        everything
+    }
+
+    /// Checks whether this `Regex` and `other` match exactly the same strings. Pairs of states
+    /// reachable by deriving both by the same `char`s are explored until one state of a pair
+    /// accepts and the other doesn't. Only the `char`s that appear in either pattern (and one
+    /// for everything in between) need to be tried.
+    ///
+    /// Like for `canonicalize`, normalizers and boundaries are ignored, and like `walk_states`,
+    /// this can fail to terminate for patterns whose derivatives keep growing.
+    pub fn equivalent(&self, other: &Regex) -> bool {
+        let mut points = self.alphabet().chars;
+        points.extend(other.alphabet().chars);
+        let classes = CharRange::partition(points);
+        let mut pairs = vec![(self.clone(), other.clone())];
+        let mut worklist = VecDeque::from([0]);
+
+        while let Some(i) = worklist.pop_front() {
+            let (r1, r2) = &pairs[i];
+            if r1.nullable() != r2.nullable() {
+                return false;
+            }
+            // SAFETY: both trees are owned by live `Regex`es
+            if unsafe { Re::const_eq(r1.tree, r2.tree) } {
+                // the same state on both sides, so everything after it is the same too
+                continue;
+            }
+            let ders: Vec<_> = (classes.iter())
+                .map(|&class| (r1.derivative_by_class(class), r2.derivative_by_class(class)))
+                .collect();
+            for (d1, d2) in ders {
+                // SAFETY: as above
+                let seen = pairs.iter().any(|(s1, s2)| unsafe {
+                    Re::const_eq(s1.tree, d1.tree) && Re::const_eq(s2.tree, d2.tree)
+                });
+                if !seen {
+                    pairs.push((d1, d2));
+                    worklist.push_back(pairs.len() - 1);
+                }
+            }
+        }
+        true
+    }
+
+    /// One `char` for every distinct (simplified) derivative of this `Regex`, along with that
+    /// derivative. `char`s from different `derivative_classes` that lead to the same state are
+    /// grouped together, and only the first one of them is kept.
+    fn distinct_derivatives(&self) -> Vec<(char, Regex<'static>)> {
+        let mut derivatives: Vec<(char, Regex<'static>)> = Vec::new();
+        for class in self.derivative_classes() {
+            let d = self.derivative_by_class(class);
+            // SAFETY: both trees are owned by live `Regex`es
+            if !derivatives
+                .iter()
+                .any(|(_, e)| unsafe { Re::const_eq(e.tree, d.tree) })
+            {
+                derivatives.push((class.representative(), d));
+            }
+        }
+        derivatives
+    }
+
+    /// Lists the strings of at most `max_len` chars that this `Regex` accepts, shortest first.
+    ///
+    /// Strings that only differ in `char`s that the `Regex` can't tell apart are only listed once,
+    /// using a representative `char`. For example `[a-z]a` only lists `"aa"`. This keeps the
+    /// output finite (and small) for patterns with very large classes, but it means the strings
+    /// listed are representatives of the language, not the whole language.
+    pub fn enumerate(&self, max_len: usize) -> Vec<String> {
+        let mut found = Vec::new();
+        let mut frontier = vec![(String::new(), self.clone())];
+        for len in 0..=max_len {
+            let mut next = Vec::new();
+            for (s, r) in frontier {
+                if r.nullable() {
+                    found.push(s.clone());
+                }
+                if len == max_len {
+                    continue;
+                }
+                for (c, d) in r.distinct_derivatives() {
+                    // SAFETY: dereferencing a reference to immutable buffers
+                    if !matches!(unsafe { d.tree.as_ref() }, Re::Zero) {
+                        next.push((format!("{}{}", s, c), d));
+                    }
+                }
+            }
+            frontier = next;
+        }
+        found
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hasher};
+
+use super::*;
+
+/// The derivative states of a `Regex` met so far, and the transitions between them, so that
+/// matching the same `Regex` over and over only derives each state by each symbol once.
+///
+/// This trades memory for speed: the cache only ever grows, by one state per distinct (simplified)
+/// derivative and one entry per transition taken, until it is `clear`ed. Patterns whose
+/// derivatives keep growing can make it grow without bound.
+pub struct DfaCache<A: Alphabet = Chars> {
+    /// Indexed by state id. The start state is always `0`.
+    states: Vec<Regex<'static, A>>,
+    accepting: Vec<bool>,
+    /// State ids by the hash of their tree. Ids with the same hash are told apart by `const_eq`.
+    ids: HashMap<u64, Vec<usize>>,
+    transitions: HashMap<(usize, A::Symbol), usize>,
+}
+
+impl<A: Alphabet> DfaCache<A> {
+    pub fn new(regex: &Regex<'_, A>) -> Self {
+        let mut cache = Self {
+            states: Vec::new(),
+            accepting: Vec::new(),
+            ids: HashMap::new(),
+            transitions: HashMap::new(),
+        };
+        cache.id(regex.clone());
+        cache
+    }
+
+    /// The id of `state`, adding it if it is new.
+    fn id(&mut self, state: Regex<'static, A>) -> usize {
+        let mut hasher = DefaultHasher::new();
+        state.canonical_hash(&mut hasher);
+        let ids = self.ids.entry(hasher.finish()).or_default();
+        if let Some(&id) = ids.iter().find(|&&id| self.states[id] == state) {
+            return id;
+        }
+        let id = self.states.len();
+        ids.push(id);
+        self.accepting.push(state.nullable());
+        self.states.push(state);
+        id
+    }
+
+    /// The state after `c` from state `id`, deriving it only if it hasn't been seen before.
+    pub fn next(&mut self, id: usize, c: A::Symbol) -> usize {
+        if let Some(&next) = self.transitions.get(&(id, c)) {
+            return next;
+        }
+        let d = self.states[id].der(c).simp().clone();
+        let next = self.id(d);
+        self.transitions.insert((id, c), next);
+        next
+    }
+
+    pub fn is_accepting(&self, id: usize) -> bool {
+        self.accepting[id]
+    }
+
+    pub fn is_dead(&self, id: usize) -> bool {
+        // SAFETY: dereferencing a reference to immutable buffers
+        matches!(unsafe { self.states[id].tree.as_ref() }, Re::Zero)
+    }
+
+    /// The state with this id.
+    pub fn state(&self, id: usize) -> &Regex<'static, A> {
+        &self.states[id]
+    }
+
+    /// How many distinct states have been cached.
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Forgets every state but the start state, and every transition.
+    pub fn clear(&mut self) {
+        let start = self.states.swap_remove(0);
+        *self = Self::new(&start);
+    }
+}
+
+/// Like `Matcher`, but goes through a `DfaCache`, so that feeding a symbol that has been fed in
+/// the same state before is just a lookup. The cache is kept across `reset`s and `is_match`es.
+pub struct CachedMatcher<A: Alphabet = Chars> {
+    cache: DfaCache<A>,
+    state: usize,
+    normalizer: Option<Normalizer<A::Symbol>>,
+}
+
+impl<A: Alphabet> CachedMatcher<A> {
+    pub fn feed(&mut self, c: A::Symbol) {
+        let c = match &self.normalizer {
+            Some(f) => f(c),
+            None => c,
+        };
+        self.state = self.cache.next(self.state, c);
+    }
+
+    pub fn is_accepting(&self) -> bool {
+        self.cache.is_accepting(self.state)
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.cache.is_dead(self.state)
+    }
+
+    /// Goes back to the start state, keeping everything cached so far.
+    pub fn reset(&mut self) {
+        self.state = 0;
+    }
+
+    /// Checks whether the `Regex` accepts exactly the symbols of `s`, like `is_match_symbols`.
+    /// Boundaries are not checked.
+    pub fn is_match(&mut self, s: impl IntoIterator<Item = A::Symbol>) -> bool {
+        self.reset();
+        for c in s {
+            self.feed(c);
+            if self.is_dead() {
+                return false;
+            }
+        }
+        self.is_accepting()
+    }
+
+    pub fn cache(&self) -> &DfaCache<A> {
+        &self.cache
+    }
+}
+
+impl<'a, A: Alphabet> Regex<'a, A> {
+    /// A matcher that caches every state and transition it goes through, see `DfaCache`.
+    pub fn matcher_cached(&self) -> CachedMatcher<A> {
+        CachedMatcher {
+            cache: DfaCache::new(self),
+            state: 0,
+            normalizer: self.normalizer.clone(),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::{error, fmt};
+
+use regex_syntax::hir::{Class, ClassUnicode, ClassUnicodeRange, Hir, HirKind, Repetition};
+
+use super::build_plan::{self, ImplicitRe};
+use super::Regex;
+
+/// Something `regex_syntax` can parse, but that we can't model (yet).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnsupportedFeature {
+    /// Capture groups, we only answer 'does it match'.
+    Capture,
+    /// Anchors, word boundaries and any other look-around assertion.
+    Look,
+    /// A literal or byte class that isn't valid UTF-8.
+    Bytes,
+}
+
+impl fmt::Display for UnsupportedFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Capture => write!(f, "capture groups are not supported"),
+            Self::Look => write!(f, "look-around assertions are not supported"),
+            Self::Bytes => write!(f, "non-UTF-8 patterns are not supported"),
+        }
+    }
+}
+
+impl error::Error for UnsupportedFeature {}
+
+/// Something a `build_plan::Re` can hold, but that `regex_syntax` can't express, see `lift`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnsupportedNode {
+    /// `And`, there is no intersection in regex syntax.
+    And,
+    /// `Not`, there is no complement in regex syntax.
+    Not,
+    /// A `Repeat` of more than `u32::MAX` repetitions.
+    LargeRepeat(usize),
+}
+
+impl fmt::Display for UnsupportedNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::And => write!(f, "intersections have no regex syntax equivalent"),
+            Self::Not => write!(f, "complements have no regex syntax equivalent"),
+            Self::LargeRepeat(n) => write!(f, "{} repetitions is more than regex syntax allows", n),
+        }
+    }
+}
+
+impl error::Error for UnsupportedNode {}
+
+/// Folds `iter` with `f`, or returns `empty` if there is nothing to fold.
+fn fold_or(
+    iter: impl IntoIterator<Item = build_plan::Re>,
+    empty: build_plan::Re,
+    f: impl Fn(build_plan::Re, build_plan::Re) -> build_plan::Re,
+) -> build_plan::Re {
+    let mut iter = iter.into_iter();
+    match iter.next() {
+        Some(first) => iter.fold(first, f),
+        None => empty,
+    }
+}
+
+/// Lowers a class into a chain of `Alt`s, one `Char` or `Range` per range in it.
+fn lower_class(class: &Class) -> Result<build_plan::Re, UnsupportedFeature> {
+    let class = match class {
+        Class::Unicode(class) => class,
+        Class::Bytes(class) => match class.to_unicode_class() {
+            Some(class) => return lower_class(&Class::Unicode(class)),
+            None => return Err(UnsupportedFeature::Bytes),
+        },
+    };
+
+    Ok(fold_or(
+        class.ranges().iter().map(|r| match (r.start(), r.end()) {
+            (lo, hi) if lo == hi => build_plan::Re::Char(lo),
+            (lo, hi) => build_plan::Re::range(lo, hi),
+        }),
+        build_plan::Re::Zero,
+        |r1, r2| r1.alt(r2),
+    ))
+}
+
+fn lower_repetition(rep: &Repetition) -> Result<build_plan::Re, UnsupportedFeature> {
+    // greediness doesn't matter, we only care about the language
+    let sub = lower(&rep.sub)?;
+    let required = |n| {
+        fold_or(
+            (0..n).map(|_| sub.clone()),
+            build_plan::Re::One,
+            |r1, r2| r1.seq(r2),
+        )
+    };
+
+    Ok(match rep.max {
+        // `r{n,}` is `r{n-1}r+`
+        None if rep.min > 0 => {
+            let plus = sub.clone().plus();
+            match rep.min {
+                1 => plus,
+                min => required(min - 1).seq(plus),
+            }
+        }
+        None => sub.star(),
+        Some(max) => {
+            let required = required(rep.min);
+            // `r{n,m}` is `r{n}` followed by `m - n` nested optionals, `(r(r)?)?`
+            let optional =
+                (rep.min..max).fold(build_plan::Re::One, |acc, _| sub.clone().seq(acc).opt());
+            required.seq(optional)
+        }
+    })
+}
+
+/// Translates `hir` into the equivalent `build_plan::Re`.
+pub fn lower(hir: &Hir) -> Result<build_plan::Re, UnsupportedFeature> {
+    match hir.kind() {
+        HirKind::Empty => Ok(build_plan::Re::One),
+        HirKind::Literal(lit) => std::str::from_utf8(&lit.0)
+            .map(|s| s.re())
+            .map_err(|_| UnsupportedFeature::Bytes),
+        HirKind::Class(class) => lower_class(class),
+        HirKind::Look(_) => Err(UnsupportedFeature::Look),
+        HirKind::Repetition(rep) => lower_repetition(rep),
+        HirKind::Capture(_) => Err(UnsupportedFeature::Capture),
+        HirKind::Concat(hirs) => Ok(fold_or(
+            hirs.iter().map(lower).collect::<Result<Vec<_>, _>>()?,
+            build_plan::Re::One,
+            |r1, r2| r1.seq(r2),
+        )),
+        HirKind::Alternation(hirs) => Ok(fold_or(
+            hirs.iter().map(lower).collect::<Result<Vec<_>, _>>()?,
+            build_plan::Re::Zero,
+            |r1, r2| r1.alt(r2),
+        )),
+    }
+}
+
+impl Regex<'static> {
+    /// Builds a `Regex` from a pattern that has already been parsed by `regex_syntax`. Use this if
+    /// you'd rather rely on the same parser as the `regex` crate.
+    ///
+    /// Literals, classes, concatenation, alternation and repetition are supported. Anything else
+    /// is reported as an `UnsupportedFeature`.
+    pub fn from_hir(hir: &Hir) -> Result<Regex<'static>, UnsupportedFeature> {
+        Ok(Regex::from(&lower(hir)?))
+    }
+}
+
+fn class(ranges: impl IntoIterator<Item = (char, char)>) -> ClassUnicode {
+    ClassUnicode::new(
+        ranges
+            .into_iter()
+            .map(|(lo, hi)| ClassUnicodeRange::new(lo, hi)),
+    )
+}
+
+fn repetition(min: u32, max: Option<u32>, sub: Hir) -> Hir {
+    Hir::repetition(Repetition {
+        min,
+        max,
+        greedy: true,
+        sub: Box::new(sub),
+    })
+}
+
+/// Translates `plan` into the equivalent `Hir`, the other way around from `lower`. `Mark`s don't
+/// change what matches, so only what they mark is kept.
+pub fn lift(plan: &build_plan::Re) -> Result<Hir, UnsupportedNode> {
+    use build_plan::Re;
+
+    Ok(match plan {
+        Re::One => Hir::empty(),
+        Re::Zero => Hir::fail(),
+        Re::Char(c) => Hir::literal(c.to_string().into_bytes()),
+        Re::Range(lo, hi) => Hir::class(Class::Unicode(class([(*lo, *hi)]))),
+        Re::Any => Hir::class(Class::Unicode(class([('\0', char::MAX)]))),
+        Re::AnyOf(chars) => Hir::class(Class::Unicode(class(chars.iter().map(|&c| (c, c))))),
+        Re::NotAnyOf(chars) => {
+            let mut class = class(chars.iter().map(|&c| (c, c)));
+            class.negate();
+            Hir::class(Class::Unicode(class))
+        }
+        Re::Alt(r1, r2) => Hir::alternation(vec![lift(r1)?, lift(r2)?]),
+        Re::Seq(r1, r2) => Hir::concat(vec![lift(r1)?, lift(r2)?]),
+        Re::Star(r) => repetition(0, None, lift(r)?),
+        Re::Plus(r) => repetition(1, None, lift(r)?),
+        Re::Opt(r) => repetition(0, Some(1), lift(r)?),
+        Re::Repeat(r, n) => {
+            let n = u32::try_from(*n).map_err(|_| UnsupportedNode::LargeRepeat(*n))?;
+            repetition(n, Some(n), lift(r)?)
+        }
+        Re::Mark(_, r) => lift(r)?,
+        Re::And(..) => return Err(UnsupportedNode::And),
+        Re::Not(_) => return Err(UnsupportedNode::Not),
+    })
+}
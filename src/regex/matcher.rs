@@ -0,0 +1,41 @@
+use super::*;
+
+/// Matches input that arrives one symbol at a time, e.g. from a stream, without having to collect
+/// it first. Boundaries are not checked, since they depend on input around the match.
+pub struct Matcher<A: Alphabet = Chars> {
+    state: Regex<'static, A>,
+}
+
+impl<A: Alphabet> Matcher<A> {
+    /// Starts matching against `regex`, with nothing fed yet.
+    pub fn new(regex: &Regex<'_, A>) -> Self {
+        Self {
+            state: regex.clone(),
+        }
+    }
+
+    /// Moves on to the state after `c`. Once the matcher is dead, this does nothing.
+    pub fn feed(&mut self, c: A::Symbol) {
+        if self.is_dead() {
+            return;
+        }
+        self.state = self.state.der(self.state.normalize(c)).simp().clone();
+    }
+
+    /// Whether everything fed so far is a match.
+    pub fn is_accepting(&self) -> bool {
+        self.state.nullable()
+    }
+
+    /// Whether nothing that could be fed from here on would make a match, so the rest of the
+    /// input can be skipped.
+    pub fn is_dead(&self) -> bool {
+        // SAFETY: dereferencing a reference to immutable buffers
+        matches!(unsafe { self.state.tree.as_ref() }, Re::Zero)
+    }
+
+    /// The current state, i.e. the derivative of the `Regex` by everything fed so far.
+    pub fn state(&self) -> &Regex<'static, A> {
+        &self.state
+    }
+}
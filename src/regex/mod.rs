@@ -1,73 +1,373 @@
-use std::{fmt, marker::PhantomData};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    marker::PhantomData,
+    ptr::NonNull,
+    sync::{Arc, OnceLock},
+};
 
 use crate::vec_alloc::VecAlloc;
 
+pub mod alphabet;
+pub use alphabet::*;
+pub mod analysis;
 pub mod const_ptr;
 pub use const_ptr::*;
+pub mod boundary;
+pub use boundary::*;
+mod budget;
+mod build_error;
+pub use build_error::*;
 pub mod build_plan;
+pub use budget::*;
+mod builder;
+pub use builder::*;
+mod cache;
+pub use cache::*;
+mod canonical;
+pub use canonical::*;
+mod captures;
+mod char_range;
+pub use char_range::*;
+mod compact;
+mod dfa;
+pub use dfa::*;
+mod dfa_cache;
+pub use dfa_cache::*;
+#[cfg(feature = "regex-syntax")]
+pub mod hir;
+mod matcher;
+pub use matcher::*;
+pub mod parse;
+mod reader;
+mod rejection;
+pub use rejection::*;
+mod transform;
+pub use transform::*;
 
 #[cfg(test)]
 mod test;
 
+#[cfg(test)]
+thread_local! {
+    /// How many times `der` or `simp` have run out of space and restarted on this thread.
+    static RESTARTS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
 #[derive(Clone, Copy)]
-pub enum Re {
+pub enum Re<A: Alphabet = Chars> {
     Zero,
     One,
-    Char(char),
-    Alt(Const<Re>, Const<Re>),
-    Seq(Const<Re>, Const<Re>),
-    Star(Const<Re>),
+    Char(A::Class),
+    /// Any one symbol in an inclusive range of classes.
+    Range(A::Class, A::Class),
+    /// Any one symbol at all.
+    Any,
+    /// Any one symbol in a set of classes. The set is the `usize` `Char`s allocated one after the
+    /// other from the pointer on, sorted and without duplicates, see `any_of`. The set lives in
+    /// the allocator like any other child, instead of in a `Box<[_]>`, so that `Re` stays `Copy`.
+    AnyOf(Const<Re<A>>, usize),
+    /// Any one symbol that isn't in a set of classes, laid out like the set of an `AnyOf`.
+    NotAnyOf(Const<Re<A>>, usize),
+    Alt(Const<Re<A>>, Const<Re<A>>),
+    Seq(Const<Re<A>>, Const<Re<A>>),
+    And(Const<Re<A>>, Const<Re<A>>),
+    Star(Const<Re<A>>),
+    Plus(Const<Re<A>>),
+    Opt(Const<Re<A>>),
+    Not(Const<Re<A>>),
+    /// Matches the same as its child, but tags it with an id, so that `Regex::captures` can tell
+    /// where it started. Derivatives drop the tag.
+    Mark(u32, Const<Re<A>>),
 }
 
-impl Re {
-    // TODO: make #[tailcall]
+impl<A: Alphabet> Re<A> {
+    /// Whether `self` matches the empty string.
+    ///
+    /// Derivatives can get very deep, so this walks the tree with an explicit stack instead of
+    /// recursing. `Alt` still stops at the first nullable branch and `Seq` at the first one that
+    /// isn't.
     pub fn nullable(&self) -> bool {
-        match &self {
-            Re::Zero => false,
-            Re::One => true,
-            Re::Char(_) => false,
-            Re::Alt(r1, r2) => unsafe { r1.as_ref().nullable() || r2.as_ref().nullable() },
-            Re::Seq(r1, r2) => unsafe { r1.as_ref().nullable() && r2.as_ref().nullable() },
-            Re::Star(_) => true,
+        /// What to do with the value of a node once we have it.
+        enum Then<'r, A: Alphabet> {
+            /// The left of an `Alt`, so we only need `r` if it is `false`.
+            OrElse(&'r Re<A>),
+            /// The left of a `Seq` or `And`, so we only need `r` if it is `true`.
+            AndAlso(&'r Re<A>),
+            Negate,
         }
-    }
 
-    unsafe fn const_eq(lhs: Const<Re>, rhs: Const<Re>) -> bool {
-        lhs.eq(rhs, |a, b| Re::eq(a, b))
+        let mut stack = Vec::new();
+        let mut r = self;
+        loop {
+            // SAFETY (for every `as_ref`): nodes are only ever read, and they outlive `self`
+            let mut value = loop {
+                match r {
+                    Re::Zero
+                    | Re::Char(_)
+                    | Re::Range(..)
+                    | Re::Any
+                    | Re::AnyOf(..)
+                    | Re::NotAnyOf(..) => break false,
+                    Re::One | Re::Star(_) | Re::Opt(_) => break true,
+                    Re::Alt(r1, r2) => unsafe {
+                        stack.push(Then::OrElse(r2.as_ref()));
+                        r = r1.as_ref();
+                    },
+                    Re::Seq(r1, r2) | Re::And(r1, r2) => unsafe {
+                        stack.push(Then::AndAlso(r2.as_ref()));
+                        r = r1.as_ref();
+                    },
+                    Re::Plus(r1) | Re::Mark(_, r1) => r = unsafe { r1.as_ref() },
+                    Re::Not(r1) => {
+                        stack.push(Then::Negate);
+                        r = unsafe { r1.as_ref() };
+                    }
+                }
+            };
+            // unwind until there is another node that we need the value of
+            loop {
+                match stack.pop() {
+                    None => return value,
+                    Some(Then::Negate) => value = !value,
+                    Some(Then::OrElse(r2)) if !value => {
+                        r = r2;
+                        break;
+                    }
+                    Some(Then::AndAlso(r2)) if value => {
+                        r = r2;
+                        break;
+                    }
+                    // short-circuited, so `value` is the value of the whole node
+                    Some(_) => {}
+                }
+            }
+        }
     }
 
-    unsafe fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::Zero, Self::Zero) => true,
-            (Self::One, Self::One) => true,
-            (Self::Char(c), Self::Char(d)) => c == d,
-            (Self::Alt(l1, l2), Self::Alt(r1, r2)) => unsafe {
-                Self::const_eq(*l1, *r1) && Self::const_eq(*l2, *r2)
+    /// A cheap, structural check that `self` matches every string, like `.*` or `!0`. This only
+    /// looks at the shape of the tree, so it can miss universal patterns, but it never claims that
+    /// a pattern is universal when it isn't.
+    fn is_universal(&self) -> bool {
+        match self {
+            Re::Star(r) => unsafe { matches!(r.as_ref(), Re::Any) || r.as_ref().is_universal() },
+            Re::Plus(r) | Re::Opt(r) | Re::Mark(_, r) => unsafe { r.as_ref().is_universal() },
+            Re::Not(r) => unsafe { matches!(r.as_ref(), Re::Zero) },
+            Re::Alt(r1, r2) => unsafe { r1.as_ref().is_universal() || r2.as_ref().is_universal() },
+            Re::Seq(r1, r2) | Re::And(r1, r2) => unsafe {
+                r1.as_ref().is_universal() && r2.as_ref().is_universal()
             },
-            (Self::Seq(l1, l2), Self::Seq(r1, r2)) => unsafe {
-                Self::const_eq(*l1, *r1) && Self::const_eq(*l2, *r2)
-            },
-            (Self::Star(l), Self::Star(r)) => unsafe { Self::const_eq(*l, *r) },
             _ => false,
         }
     }
+
+    /// Structural equality, with the same pointer-equality fast path as `const_cmp`. Goes through
+    /// `const_cmp` so that `==` and `Ord` can never disagree, and so that deep trees don't recurse.
+    ///
+    /// ## Safety
+    /// The same as for `const_cmp`.
+    unsafe fn const_eq(lhs: Const<Re<A>>, rhs: Const<Re<A>>) -> bool {
+        unsafe { Self::const_cmp(lhs, rhs) == Ordering::Equal }
+    }
+
+    unsafe fn eq(&self, other: &Self) -> bool {
+        unsafe { Self::const_eq(self.into(), other.into()) }
+    }
+
+    /// The position of this kind of node in the order `const_cmp` puts trees in.
+    fn rank(&self) -> u8 {
+        match self {
+            Re::Zero => 0,
+            Re::One => 1,
+            Re::Char(_) => 2,
+            Re::Range(..) => 3,
+            Re::Any => 4,
+            Re::AnyOf(..) => 5,
+            Re::NotAnyOf(..) => 6,
+            Re::Alt(..) => 7,
+            Re::Seq(..) => 8,
+            Re::And(..) => 9,
+            Re::Star(_) => 10,
+            Re::Plus(_) => 11,
+            Re::Opt(_) => 12,
+            Re::Not(_) => 13,
+            Re::Mark(..) => 14,
+        }
+    }
+
+    /// Folds the kind and payload of every node reachable from `r` into `state`, in pre-order, so
+    /// that trees that are `const_eq` hash the same wherever their nodes are allocated.
+    ///
+    /// ## Safety
+    /// Every node reachable from `r` must be valid for reads. `Const` doesn't track which
+    /// allocator its node is in, so that allocator must not be resized or dropped meanwhile.
+    unsafe fn hash_tree(r: Const<Re<A>>, state: &mut impl Hasher) {
+        let mut stack = vec![r];
+        while let Some(r) = stack.pop() {
+            let r = r.read();
+            r.rank().hash(state);
+            match r {
+                Re::Zero | Re::One | Re::Any => {}
+                Re::Char(c) => c.hash(state),
+                Re::Range(lo, hi) => (lo, hi).hash(state),
+                Re::AnyOf(set, len) | Re::NotAnyOf(set, len) => {
+                    any_of(set, len).for_each(|c| c.hash(state))
+                }
+                Re::Alt(r1, r2) | Re::Seq(r1, r2) | Re::And(r1, r2) => {
+                    stack.push(r2);
+                    stack.push(r1);
+                }
+                Re::Star(r) | Re::Plus(r) | Re::Opt(r) | Re::Not(r) => stack.push(r),
+                Re::Mark(id, r) => {
+                    id.hash(state);
+                    stack.push(r);
+                }
+            }
+        }
+    }
+
+    /// A total order on trees that agrees with `const_eq`: by the kind of node first, then by its
+    /// payload, then by its children from left to right.
+    ///
+    /// ## Safety
+    /// The same as for `hash_tree`, for both trees.
+    unsafe fn const_cmp(lhs: Const<Re<A>>, rhs: Const<Re<A>>) -> Ordering {
+        let mut stack = vec![(lhs, rhs)];
+        while let Some((l, r)) = stack.pop() {
+            // shared subtrees are common after `repeat` and `simp`, and are trivially equal
+            if l.ptr_eq(r) {
+                continue;
+            }
+            let (l, r) = (l.read(), r.read());
+            let ord = l.rank().cmp(&r.rank()).then_with(|| match (l, r) {
+                (Re::Char(c), Re::Char(d)) => c.cmp(&d),
+                (Re::Range(lo1, hi1), Re::Range(lo2, hi2)) => (lo1, hi1).cmp(&(lo2, hi2)),
+                (Re::AnyOf(s1, len1), Re::AnyOf(s2, len2))
+                | (Re::NotAnyOf(s1, len1), Re::NotAnyOf(s2, len2)) => {
+                    any_of(s1, len1).cmp(any_of(s2, len2))
+                }
+                (Re::Mark(id1, _), Re::Mark(id2, _)) => id1.cmp(&id2),
+                _ => Ordering::Equal,
+            });
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            match (l, r) {
+                (Re::Alt(l1, l2), Re::Alt(r1, r2))
+                | (Re::Seq(l1, l2), Re::Seq(r1, r2))
+                | (Re::And(l1, l2), Re::And(r1, r2)) => {
+                    stack.push((l2, r2));
+                    stack.push((l1, r1));
+                }
+                (Re::Star(l), Re::Star(r))
+                | (Re::Plus(l), Re::Plus(r))
+                | (Re::Opt(l), Re::Opt(r))
+                | (Re::Not(l), Re::Not(r))
+                | (Re::Mark(_, l), Re::Mark(_, r)) => stack.push((l, r)),
+                _ => {}
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// How many nodes are reachable from `r`, counting each shared node (and the `Char`s of a set)
+    /// once, and the most nodes on any path from `r` down to a leaf.
+    ///
+    /// ## Safety
+    /// The same as for `hash_tree`.
+    unsafe fn size_and_depth(r: Const<Re<A>>) -> (usize, usize) {
+        let mut depths: HashMap<NonNull<Re<A>>, usize> = HashMap::new();
+        let mut size = 0;
+        // a node is pushed a second time, as `true`, to be finished once its children are
+        let mut stack = vec![(r, false)];
+        while let Some((r, children_done)) = stack.pop() {
+            let node = r.read();
+            let children = match node {
+                Re::Alt(r1, r2) | Re::Seq(r1, r2) | Re::And(r1, r2) => [Some(r1), Some(r2)],
+                Re::Star(r1) | Re::Plus(r1) | Re::Opt(r1) | Re::Not(r1) | Re::Mark(_, r1) => {
+                    [Some(r1), None]
+                }
+                _ => [None, None],
+            };
+            if !children_done {
+                if !depths.contains_key(&r.as_non_null()) {
+                    stack.push((r, true));
+                    stack.extend(children.into_iter().flatten().map(|c| (c, false)));
+                }
+                continue;
+            }
+            let depth = 1 + children
+                .into_iter()
+                .flatten()
+                .map(|c| depths[&c.as_non_null()])
+                .max()
+                .unwrap_or(0);
+            if depths.insert(r.as_non_null(), depth).is_none() {
+                size += match node {
+                    Re::AnyOf(_, len) | Re::NotAnyOf(_, len) => 1 + len,
+                    _ => 1,
+                };
+            }
+        }
+        (size, depths[&r.as_non_null()])
+    }
 }
 
-pub struct Regex<'parent> {
+pub struct Regex<'parent, A: Alphabet = Chars> {
     // We require each Regex to point to something for the head. Regexes can be moved around, so it
     // creates serious complications otherwise.
-    tree: Const<Re>,
-    alloc: VecAlloc<Re>,
+    tree: Const<Re<A>>,
+    alloc: VecAlloc<Re<A>>,
+    normalizer: Option<Normalizer<A::Symbol>>,
+    /// Computed on first use, see `Regex::analyses`. Derivatives are different `Regex`es, so they
+    /// start out empty.
+    analyses: OnceLock<analysis::Analyses<A>>,
+    /// Checked around matches by the search methods, see `Regex::with_boundaries`.
+    boundaries: Boundaries,
     phantom: PhantomData<&'parent ()>,
 }
 
-impl fmt::Debug for Re {
+/// Maps every input symbol before it is matched, see `Regex::with_char_normalizer`.
+type Normalizer<S> = Arc<dyn Fn(S) -> S + Send + Sync>;
+
+// SAFETY: a `Regex` never mutates its tree or its parent's tree through a shared reference, so
+// reading them from several threads at once is fine. Everything else is owned.
+unsafe impl<A: Alphabet> Send for Regex<'_, A> {}
+unsafe impl<A: Alphabet> Sync for Regex<'_, A> {}
+
+impl<A: Alphabet> fmt::Debug for Re<A> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fn fmt_rec(r: &Re, unit: bool) -> String {
+        /// `r`, in parentheses unless it is a single atom.
+        fn atom<A: Alphabet>(r: Const<Re<A>>) -> String {
+            match unsafe { r.read() } {
+                r @ (Re::Alt(..)
+                | Re::Seq(..)
+                | Re::And(..)
+                | Re::Star(_)
+                | Re::Plus(_)
+                | Re::Opt(_)
+                | Re::Not(_)) => format!("({:?})", r),
+                r => format!("{:?}", r),
+            }
+        }
+
+        fn fmt_rec<A: Alphabet>(r: &Re<A>, unit: bool) -> String {
             match (r, unit) {
-                (Re::Zero, _) => format!("0"),
-                (Re::One, _) => format!("1"),
+                (Re::Zero, _) => "0".to_string(),
+                (Re::One, _) => "1".to_string(),
                 (Re::Char(c), _) => format!("{:?}", c),
+                (Re::Range(lo, hi), _) => format!("[{:?}-{:?}]", lo, hi),
+                (Re::Any, _) => ".".to_string(),
+                (Re::AnyOf(set, len), _) => unsafe {
+                    let set: Vec<_> = any_of(*set, *len).map(|c| format!("{:?}", c)).collect();
+                    format!("[{}]", set.join(" "))
+                },
+                (Re::NotAnyOf(set, len), _) => unsafe {
+                    let set: Vec<_> = any_of(*set, *len).map(|c| format!("{:?}", c)).collect();
+                    format!("[^{}]", set.join(" "))
+                },
                 (Re::Seq(r1, r2), _) => unsafe {
                     format!(
                         "{}.{}",
@@ -78,35 +378,28 @@ impl fmt::Debug for Re {
                 (Re::Alt(r1, r2), false) => unsafe {
                     format!(
                         "({}|{})",
-                        fmt_rec(
-                            &r1.read(),
-                            if let Re::Alt(..) = r1.read() {
-                                true
-                            } else {
-                                false
-                            }
-                        ),
-                        fmt_rec(
-                            &r2.read(),
-                            if let Re::Alt(..) = r2.read() {
-                                true
-                            } else {
-                                false
-                            }
-                        )
+                        fmt_rec(&r1.read(), matches!(r1.read(), Re::Alt(..))),
+                        fmt_rec(&r2.read(), matches!(r2.read(), Re::Alt(..)))
                     )
                 },
                 (Re::Alt(r1, r2), true) => unsafe { format!("{:?}|{:?}", r1.read(), r2.read()) },
-                (Re::Star(r), _) => unsafe {
-                    match r.read() {
-                        Re::Seq(..) | Re::Star(_) => {
-                            format!("({:?})*", r.read())
-                        }
-                        _ => {
-                            format!("{:?}*", r.read())
-                        }
+                (Re::And(r1, r2), unit) => unsafe {
+                    let and = format!(
+                        "{}&{}",
+                        fmt_rec(&r1.read(), matches!(r1.read(), Re::And(..))),
+                        fmt_rec(&r2.read(), matches!(r2.read(), Re::And(..)))
+                    );
+                    if unit {
+                        and
+                    } else {
+                        format!("({})", and)
                     }
                 },
+                (Re::Star(r), _) => format!("{}*", atom(*r)),
+                (Re::Plus(r), _) => format!("{}+", atom(*r)),
+                (Re::Opt(r), _) => format!("{}?", atom(*r)),
+                (Re::Not(r), _) => format!("!{}", atom(*r)),
+                (Re::Mark(id, r), _) => unsafe { format!("<{}:{:?}>", id, r.read()) },
             }
         }
 
@@ -114,101 +407,495 @@ impl fmt::Debug for Re {
     }
 }
 
-impl fmt::Debug for Regex<'_> {
+/// Structural equality: two `Regex`es are equal if their trees have the same shape and the same
+/// `Char`s, wherever their nodes are allocated. This is not language equality, `a|b` and `b|a` are
+/// different, see `CanonicalRegex` for that. Normalizers and boundaries aren't compared.
+impl<A: Alphabet> PartialEq for Regex<'_, A> {
+    fn eq(&self, other: &Self) -> bool {
+        // SAFETY: both trees are owned by live `Regex`es, and are only read
+        unsafe { Re::const_eq(self.tree, other.tree) }
+    }
+}
+
+impl<A: Alphabet> Eq for Regex<'_, A> {}
+
+/// Consistent with `PartialEq`, so only the tree is hashed. See `Regex::canonical_hash`.
+impl<A: Alphabet> Hash for Regex<'_, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical_hash(state)
+    }
+}
+
+/// An arbitrary but deterministic structural order, e.g. for sorting or deduplicating states.
+/// Consistent with `PartialEq`.
+impl<A: Alphabet> Ord for Regex<'_, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // SAFETY: both trees are owned by live `Regex`es, and are only read
+        unsafe { Re::const_cmp(self.tree, other.tree) }
+    }
+}
+
+impl<A: Alphabet> PartialOrd for Regex<'_, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<A: Alphabet> fmt::Debug for Regex<'_, A> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Regex({:?})", unsafe { self.tree.as_ref() },)
     }
 }
 
+fn try_alloc<A: Alphabet>(alloc: &mut VecAlloc<Re<A>>, value: Re<A>) -> Result<Const<Re<A>>, ()> {
+    alloc.alloc(value).map(Const::new).map_err(|_| ())
+}
+
+/// `r` `n` times in a row. Every `Seq` is the same pattern repeated, so rather than `n` copies,
+/// this builds `r` repeated 2^k times for every bit k of `n`, each out of two pointers to the one
+/// before it, and strings together the ones for the bits that are set. That takes O(log n) nodes.
+fn repeat<A: Alphabet>(
+    alloc: &mut VecAlloc<Re<A>>,
+    r: Const<Re<A>>,
+    mut n: usize,
+) -> Result<Const<Re<A>>, ()> {
+    let mut acc = None;
+    let mut power = r;
+    while n > 0 {
+        if n & 1 == 1 {
+            acc = Some(match acc {
+                Some(acc) => try_alloc(alloc, Re::Seq(power, acc))?,
+                None => power,
+            });
+        }
+        n >>= 1;
+        if n > 0 {
+            power = try_alloc(alloc, Re::Seq(power, power))?;
+        }
+    }
+    match acc {
+        Some(acc) => Ok(acc),
+        None => try_alloc(alloc, Re::One),
+    }
+}
+
+/// Allocates `classes` one after the other, as the set of an `AnyOf` or `NotAnyOf`, and returns
+/// the first of them. There has to be at least one.
+fn alloc_set<A: Alphabet>(
+    alloc: &mut VecAlloc<Re<A>>,
+    classes: impl IntoIterator<Item = A::Class>,
+) -> Result<Const<Re<A>>, ()> {
+    let mut classes = classes.into_iter();
+    let first = try_alloc(alloc, Re::Char(classes.next().expect("sets aren't empty")))?;
+    for class in classes {
+        try_alloc(alloc, Re::Char(class))?;
+    }
+    Ok(first)
+}
+
+/// The `Char`s of the set of an `AnyOf(first, len)`.
+///
+/// ## Safety
+/// `first` must point at `len` `Char`s allocated one after the other, valid for reads for `'r`.
+unsafe fn any_of_nodes<'r, A: Alphabet>(first: Const<Re<A>>, len: usize) -> &'r [Re<A>] {
+    unsafe { std::slice::from_raw_parts(first.as_non_null().as_ptr(), len) }
+}
+
+fn set_class<A: Alphabet>(r: &Re<A>) -> A::Class {
+    match r {
+        Re::Char(c) => *c,
+        _ => unreachable!("sets only hold `Char`s"),
+    }
+}
+
+/// The classes of the set of an `AnyOf(first, len)`, in order.
+///
+/// ## Safety
+/// The same as for `any_of_nodes`, for as long as the iterator is used.
+unsafe fn any_of<'r, A: Alphabet>(
+    first: Const<Re<A>>,
+    len: usize,
+) -> impl Iterator<Item = A::Class> + 'r {
+    unsafe { any_of_nodes(first, len) }.iter().map(set_class)
+}
+
+/// Whether `c` is in the set of an `AnyOf(first, len)`, by binary search.
+///
+/// ## Safety
+/// The same as for `any_of_nodes`.
+unsafe fn any_of_contains<A: Alphabet>(first: Const<Re<A>>, len: usize, c: A::Symbol) -> bool {
+    let set = unsafe { any_of_nodes(first, len) };
+    let (Some(lo), Some(hi)) = (set.first().map(set_class), set.last().map(set_class)) else {
+        return false;
+    };
+    // `Alphabet` can only compare a symbol with classes through `in_range`, so look for the first
+    // class that `c` isn't past
+    if !A::in_range(&lo, &hi, c) {
+        return false;
+    }
+    let i = set.partition_point(|r| !A::in_range(&lo, &set_class(r), c));
+    A::matches(&set_class(&set[i]), c)
+}
+
+/// The branches of the right-leaning spine of `Alt`s starting at `r`, or just `r` if it isn't an
+/// `Alt`.
+///
+/// ## Safety
+/// Every node on the spine must be valid for reads for as long as the iterator is used.
+unsafe fn alt_spine<A: Alphabet>(r: Const<Re<A>>) -> impl Iterator<Item = Const<Re<A>>> {
+    let mut next = Some(r);
+    std::iter::from_fn(move || {
+        let r = next?;
+        // SAFETY: guaranteed by the caller
+        match unsafe { r.read() } {
+            Re::Alt(r1, r2) => {
+                next = Some(r2);
+                Some(r1)
+            }
+            _ => {
+                next = None;
+                Some(r)
+            }
+        }
+    })
+}
+
+/// The parts of the right-leaning spine of `Seq`s starting at `r`, or just `r` if it isn't a
+/// `Seq`.
+///
+/// A `Seq` with the same node on both sides is a repetition made by `repeat`, and counts as a
+/// single part. Going into it would spell the repetition out.
+///
+/// ## Safety
+/// Every node on the spine must be valid for reads for as long as the iterator is used.
+unsafe fn seq_spine<A: Alphabet>(r: Const<Re<A>>) -> impl Iterator<Item = Const<Re<A>>> {
+    let mut next = Some(r);
+    std::iter::from_fn(move || {
+        let r = next?;
+        // SAFETY: guaranteed by the caller
+        match unsafe { r.read() } {
+            Re::Seq(r1, r2) if !r1.ptr_eq(r2) => {
+                next = Some(r2);
+                Some(r1)
+            }
+            _ => {
+                next = None;
+                Some(r)
+            }
+        }
+    })
+}
+
+/// Whether `r` is `s*`.
+///
+/// ## Safety
+/// `r` and `s` must be valid for reads.
+unsafe fn is_star_of<A: Alphabet>(r: Const<Re<A>>, s: Const<Re<A>>) -> bool {
+    // SAFETY: guaranteed by the caller
+    matches!(unsafe { r.as_ref() }, Re::Star(r1) if Re::const_eq(*r1, s))
+}
+
 impl<'a> From<&build_plan::Re> for Regex<'a> {
     fn from(value: &build_plan::Re) -> Self {
-        fn build_inner(
-            alloc: &mut VecAlloc<Re>,
-            root: &build_plan::Re,
-            build_plan: &build_plan::Re,
-        ) -> Const<Re> {
-            let try_alloc = |alloc: &mut VecAlloc<Re>, value: Re| {
-                alloc.alloc(value).map_or_else(
-                    |_| build_inner(alloc.resized(), root, root),
-                    |v| Const::new(v),
-                )
-            };
+        Self::from_plan(value)
+    }
+}
 
-            match build_plan {
-                build_plan::Re::One => try_alloc(alloc, Re::One),
-                build_plan::Re::Zero => try_alloc(alloc, Re::Zero),
-                build_plan::Re::Char(c) => try_alloc(alloc, Re::Char(*c)),
-                build_plan::Re::Alt(r1, r2) => {
-                    let r1 = build_inner(alloc, root, r1.as_ref());
-                    let r2 = build_inner(alloc, root, r2.as_ref());
-                    try_alloc(alloc, Re::Alt(r1, r2))
-                }
-                build_plan::Re::Seq(r1, r2) => {
-                    let r1 = build_inner(alloc, root, r1.as_ref());
-                    let r2 = build_inner(alloc, root, r2.as_ref());
-                    try_alloc(alloc, Re::Seq(r1, r2))
-                }
-                build_plan::Re::Star(r) => {
-                    let r = Re::Star(build_inner(alloc, root, r.as_ref()));
-                    try_alloc(alloc, r)
+impl<'a, A: Alphabet> Regex<'a, A> {
+    /// Builds `plan` over any `Alphabet`, spelling each of its `char`s with `Alphabet::encode`.
+    /// `Regex::from` does the same for the default `Chars` alphabet.
+    pub fn from_plan(plan: &build_plan::Re) -> Self {
+        match Self::build_limited(plan, usize::MAX) {
+            Ok(r) => r,
+            Err(e) => unreachable!("{}", e),
+        }
+    }
+
+    /// Like `from_plan`, but checks `plan` first, see `BuildError`.
+    pub fn try_from_plan(plan: &build_plan::Re) -> Result<Self, BuildError> {
+        Self::try_from_plan_with_limit(plan, usize::MAX)
+    }
+
+    /// Like `try_from_plan`, but gives up once more than `max_nodes` nodes would be needed,
+    /// instead of growing the allocator without bound.
+    pub fn try_from_plan_with_limit(
+        plan: &build_plan::Re,
+        max_nodes: usize,
+    ) -> Result<Self, BuildError> {
+        BuildError::check(plan)?;
+        Self::build_limited(plan, max_nodes)
+    }
+
+    /// Builds `plan` into an allocator of at most `limit` nodes.
+    fn build_limited(plan: &build_plan::Re, limit: usize) -> Result<Self, BuildError> {
+        enum Frame<'p> {
+            /// Build the children of this node.
+            Enter(&'p build_plan::Re),
+            /// The children of this node are on top of the `built` stack, so build the node.
+            Exit(&'p build_plan::Re),
+            /// The parts of a concatenation are on top of the `built` stack, so join them up.
+            Concat(usize),
+        }
+
+        // Like `der_rec`, we bail out as soon as the allocator is full, since every pointer we
+        // have built so far dangles after a resize. The caller restarts from the root.
+        //
+        // Plans can be very deep (e.g. desugared bounded repetition), so this is a post-order
+        // traversal with an explicit stack, instead of recursion.
+        fn build_inner<A: Alphabet>(
+            alloc: &mut VecAlloc<Re<A>>,
+            build_plan: &build_plan::Re,
+        ) -> Result<Const<Re<A>>, ()> {
+            let mut stack = vec![Frame::Enter(build_plan)];
+            let mut built = Vec::new();
+            while let Some(frame) = stack.pop() {
+                match frame {
+                    Frame::Enter(plan) => match plan {
+                        build_plan::Re::One => built.push(try_alloc(alloc, Re::One)?),
+                        build_plan::Re::Zero => built.push(try_alloc(alloc, Re::Zero)?),
+                        build_plan::Re::Char(c) => {
+                            // `c` becomes a `Seq` of its classes, built from the back so that
+                            // it nests to the right like every other `Seq`
+                            let classes: Vec<A::Class> = A::encode(*c).collect();
+                            let mut r = match classes.last() {
+                                Some(class) => try_alloc(alloc, Re::Char(*class))?,
+                                None => try_alloc(alloc, Re::One)?,
+                            };
+                            for class in classes.iter().rev().skip(1) {
+                                let head = try_alloc(alloc, Re::Char(*class))?;
+                                r = try_alloc(alloc, Re::Seq(head, r))?;
+                            }
+                            built.push(r);
+                        }
+                        build_plan::Re::Any => match A::encode_any() {
+                            None => built.push(try_alloc(alloc, Re::Any)?),
+                            Some(spellings) => {
+                                let mut alts = None;
+                                for spelling in spellings {
+                                    let mut ranges = spelling.iter().rev();
+                                    let (lo, hi) = ranges.next().unwrap();
+                                    let mut r = try_alloc(alloc, Re::Range(*lo, *hi))?;
+                                    for (lo, hi) in ranges {
+                                        let head = try_alloc(alloc, Re::Range(*lo, *hi))?;
+                                        r = try_alloc(alloc, Re::Seq(head, r))?;
+                                    }
+                                    alts = Some(match alts {
+                                        Some(alts) => try_alloc(alloc, Re::Alt(alts, r))?,
+                                        None => r,
+                                    });
+                                }
+                                built.push(alts.unwrap());
+                            }
+                        },
+                        build_plan::Re::AnyOf(chars) | build_plan::Re::NotAnyOf(chars) => {
+                            let negated = matches!(plan, build_plan::Re::NotAnyOf(_));
+                            let classes: Option<BTreeSet<A::Class>> = chars
+                                .iter()
+                                .map(|&c| match A::encode(c).collect::<Vec<_>>()[..] {
+                                    [class] => Some(class),
+                                    _ => None,
+                                })
+                                .collect();
+                            match classes {
+                                // a negated set also has to know that every symbol is a `char`
+                                Some(classes)
+                                    if !classes.is_empty()
+                                        && (!negated || A::encode_any().is_none()) =>
+                                {
+                                    let len = classes.len();
+                                    let first = alloc_set(alloc, classes)?;
+                                    let r = if negated {
+                                        Re::NotAnyOf(first, len)
+                                    } else {
+                                        Re::AnyOf(first, len)
+                                    };
+                                    built.push(try_alloc(alloc, r)?);
+                                }
+                                // otherwise spell out every `char` instead
+                                _ => {
+                                    let mut alts = chars.iter().map(|&c| build_plan::Re::Char(c));
+                                    let alts = match alts.next() {
+                                        Some(first) => {
+                                            alts.fold(first, build_plan::ImplicitRe::alt)
+                                        }
+                                        None => build_plan::Re::Zero,
+                                    };
+                                    let r = if negated {
+                                        build_plan::ImplicitRe::and(
+                                            build_plan::Re::Any,
+                                            build_plan::ImplicitRe::complement(alts),
+                                        )
+                                    } else {
+                                        alts
+                                    };
+                                    built.push(build_inner(alloc, &r)?);
+                                }
+                            }
+                        }
+                        build_plan::Re::Range(lo, hi) => match A::encode_range(*lo, *hi) {
+                            Some((lo, hi)) => built.push(try_alloc(alloc, Re::Range(lo, hi))?),
+                            // spell out every `char` in the range instead
+                            None => {
+                                let mut chars = (*lo..=*hi).map(build_plan::Re::Char);
+                                let first = chars.next().unwrap();
+                                let alts = chars.fold(first, build_plan::ImplicitRe::alt);
+                                built.push(build_inner(alloc, &alts)?);
+                            }
+                        },
+                        build_plan::Re::Seq(..) => {
+                            // Plans nest concatenations either way (strings nest to the left), so
+                            // take the whole chain at once, and join it up to the right. Doing it
+                            // one `Seq` at a time would copy the chain over and over.
+                            let mut parts = Vec::new();
+                            let mut todo = vec![plan];
+                            while let Some(r) = todo.pop() {
+                                match r {
+                                    build_plan::Re::Seq(r1, r2) => {
+                                        todo.push(r2);
+                                        todo.push(r1);
+                                    }
+                                    _ => parts.push(r),
+                                }
+                            }
+                            stack.push(Frame::Concat(parts.len()));
+                            stack.extend(parts.into_iter().rev().map(Frame::Enter));
+                        }
+                        build_plan::Re::Alt(r1, r2) | build_plan::Re::And(r1, r2) => {
+                            stack.push(Frame::Exit(plan));
+                            stack.push(Frame::Enter(r2));
+                            stack.push(Frame::Enter(r1));
+                        }
+                        build_plan::Re::Star(r)
+                        | build_plan::Re::Plus(r)
+                        | build_plan::Re::Opt(r)
+                        | build_plan::Re::Not(r)
+                        | build_plan::Re::Mark(_, r)
+                        | build_plan::Re::Repeat(r, _) => {
+                            stack.push(Frame::Exit(plan));
+                            stack.push(Frame::Enter(r));
+                        }
+                    },
+                    Frame::Concat(n) => {
+                        let parts = built.split_off(built.len() - n);
+                        // SAFETY: every part was just built on `alloc`
+                        let parts: Vec<_> = parts
+                            .into_iter()
+                            .flat_map(|r| unsafe { seq_spine(r) })
+                            .collect();
+                        let mut r = *parts.last().unwrap();
+                        for &part in parts.iter().rev().skip(1) {
+                            r = try_alloc(alloc, Re::Seq(part, r))?;
+                        }
+                        built.push(r);
+                    }
+                    Frame::Exit(build_plan::Re::Repeat(_, n)) => {
+                        let r = built.pop().unwrap();
+                        built.push(repeat(alloc, r, *n)?);
+                    }
+                    Frame::Exit(plan) => {
+                        let r = match plan {
+                            build_plan::Re::Alt(..) => {
+                                let r2 = built.pop().unwrap();
+                                Re::Alt(built.pop().unwrap(), r2)
+                            }
+                            build_plan::Re::And(..) => {
+                                let r2 = built.pop().unwrap();
+                                Re::And(built.pop().unwrap(), r2)
+                            }
+                            build_plan::Re::Star(_) => Re::Star(built.pop().unwrap()),
+                            build_plan::Re::Plus(_) => Re::Plus(built.pop().unwrap()),
+                            build_plan::Re::Opt(_) => Re::Opt(built.pop().unwrap()),
+                            build_plan::Re::Not(_) => Re::Not(built.pop().unwrap()),
+                            build_plan::Re::Mark(id, _) => Re::Mark(*id, built.pop().unwrap()),
+                            _ => unreachable!("leaves are built on entry"),
+                        };
+                        built.push(try_alloc(alloc, r)?);
+                    }
                 }
             }
+            Ok(built.pop().unwrap())
         }
 
         // SAFETY: the allocator is allocated to using valid methods and all references are dropped
         // on resizes.
-        let mut alloc = VecAlloc::new(Regex::DEFAULT_CAPACITY);
+        let mut alloc = VecAlloc::new(Self::DEFAULT_CAPACITY.min(limit));
         // SAFETY:
         // - the tree is owned by this Regex's allocator, so it's fine.
-        //
-        // Obviously we have one 'redundany' entry in our allocator, but we'll have to live with it
-        let tree = build_inner(&mut alloc, value, value);
-        unsafe { Regex::new(tree, alloc) }
+        let tree = loop {
+            match build_inner(&mut alloc, plan) {
+                Ok(tree) => break tree,
+                Err(_) if alloc.capacity() >= limit => {
+                    return Err(BuildError::CapacityExceeded { limit })
+                }
+                Err(_) => {
+                    let capacity = alloc.capacity().saturating_mul(2).max(1).min(limit);
+                    alloc = VecAlloc::new(capacity);
+                }
+            }
+        };
+        Ok(unsafe { Self::new(tree, alloc) })
     }
-}
-
-fn try_alloc(alloc: &mut VecAlloc<Re>, value: Re) -> Result<Const<Re>, ()> {
-    alloc.alloc(value).map(|v| Const::new(v)).map_err(|_| ())
-}
 
-impl<'a> Regex<'a> {
     pub const DEFAULT_CAPACITY: usize = 32;
 
-    /// SAFETY: not unsafe, but marked as unsafe since `tree` must be owned by `alloc` for most
-    /// methods to be sound.
-    unsafe fn new(tree: Const<Re>, alloc: VecAlloc<Re>) -> Self {
+    /// How many `simp` passes to make at most when simplifying until nothing changes. A handful is
+    /// normally enough, this only guards against rewrites that undo each other.
+    const SIMP_FIXPOINT_CAP: usize = 64;
+
+    /// ## Safety
+    /// Not unsafe, but marked as unsafe since `tree` must be owned by `alloc` for most methods to
+    /// be sound.
+    unsafe fn new(tree: Const<Re<A>>, alloc: VecAlloc<Re<A>>) -> Self {
         Self {
             tree,
             alloc,
+            normalizer: None,
+            analyses: OnceLock::new(),
+            boundaries: Boundaries::default(),
             phantom: PhantomData,
         }
     }
 
-    pub fn alloc(&self) -> &VecAlloc<Re> {
+    pub fn alloc(&self) -> &VecAlloc<Re<A>> {
         &self.alloc
     }
 
-    /// Exposes unsafe access to the internal allocator. Mutating the internal allocator could
-    /// leave references into this Regex dangling.
-    pub unsafe fn alloc_mut(&mut self) -> &mut VecAlloc<Re> {
+    /// Exposes unsafe access to the internal allocator.
+    ///
+    /// ## Safety
+    /// Mutating the internal allocator could leave references into this Regex dangling.
+    pub unsafe fn alloc_mut(&mut self) -> &mut VecAlloc<Re<A>> {
         &mut self.alloc
     }
 
-    /// Exposes unsafe access to the internal tree root. This does not have any lifetime guards,
-    /// and so you can link this to anything you want, which is probably unsound. Just don't use it.
-    pub unsafe fn tree_mut(&mut self) -> &mut Const<Re> {
+    /// Exposes unsafe access to the internal tree root.
+    ///
+    /// ## Safety
+    /// This does not have any lifetime guards, and so you can link this to anything you want,
+    /// which is probably unsound. Just don't use it.
+    pub unsafe fn tree_mut(&mut self) -> &mut Const<Re<A>> {
         &mut self.tree
     }
 
     /// Produces a child `Regex`. This Regex is tied to its parent. It is likely not useful.
-    pub fn child(&'a self) -> Regex<'a> {
+    pub fn child(&'a self) -> Regex<'a, A> {
         Regex {
-            tree: self.tree.clone(),
+            tree: self.tree,
             alloc: VecAlloc::new(0),
+            normalizer: self.normalizer.clone(),
+            analyses: OnceLock::new(),
+            boundaries: Boundaries::default(),
             phantom: PhantomData,
         }
     }
 
+    fn normalize(&self, c: A::Symbol) -> A::Symbol {
+        match &self.normalizer {
+            Some(f) => f(c),
+            None => c,
+        }
+    }
+
     /// Checks if this `Regex` is 'nullable'. This means that the regex has consumed enough
     /// characters to be marked as 'complete'.
     pub fn nullable(&self) -> bool {
@@ -216,36 +903,114 @@ impl<'a> Regex<'a> {
         unsafe { self.tree.as_ref() }.nullable()
     }
 
-    /// Copies `r` into `alloc`.
-    /// SAFETY: `alloc` must not own `r`. `r` must be valid for reads and live for the duration
-    /// of the function.
-    unsafe fn rebuild_with(alloc: &mut VecAlloc<Re>, r: Const<Re>) -> Const<Re> {
-        unsafe fn rebuild_with_rec(
-            alloc: &mut VecAlloc<Re>,
-            r: Const<Re>,
-            root: Const<Re>,
-        ) -> Result<Const<Re>, ()> {
-            let r = r.read();
-            match r {
-                Re::Zero | Re::One | Re::Char(_) => try_alloc(alloc, r),
-                Re::Alt(r1, r2) => {
-                    let r1 = rebuild_with_rec(alloc, r1, root)?;
-                    let r2 = rebuild_with_rec(alloc, r2, root)?;
-                    try_alloc(alloc, Re::Alt(r1, r2))
-                }
-                Re::Seq(r1, r2) => {
-                    let r1 = rebuild_with_rec(alloc, r1, root)?;
-                    let r2 = rebuild_with_rec(alloc, r2, root)?;
-                    try_alloc(alloc, Re::Seq(r1, r2))
-                }
-                Re::Star(r) => {
-                    let r = rebuild_with_rec(alloc, r, root)?;
-                    try_alloc(alloc, Re::Star(r))
-                }
+    /// Whether this `Regex` accepts `""`. The same as `nullable`.
+    pub fn matches_empty_string(&self) -> bool {
+        self.nullable()
+    }
+
+    /// Whether this `Regex` can never match anything, e.g. for `a&!a` or `a&aa`.
+    ///
+    /// This is conservative: it checks whether `simp_full` gives `Zero`, and also
+    /// uses `length_bounds`, but simplification doesn't decide every language. A `false` doesn't
+    /// mean that something does match.
+    pub fn is_empty_language(&self) -> bool {
+        if self.length_bounds().is_none() {
+            return true;
+        }
+        let r = self.simp_full();
+        // SAFETY: dereferencing a reference to immutable buffers
+        matches!(unsafe { r.tree.as_ref() }, Re::Zero)
+    }
+
+    /// How many nodes the tree is made of. Nodes that are shared, e.g. between the copies that
+    /// `ImplicitRe::times` makes, are counted once. Useful to see how much `simp` saves, or to pick
+    /// a capacity.
+    pub fn node_count(&self) -> usize {
+        // SAFETY: the tree is owned by this `Regex` or one of its parents, which outlive it and
+        // can't be resized while it borrows them
+        unsafe { Re::size_and_depth(self.tree) }.0
+    }
+
+    /// How many nodes deep the tree is, e.g. 1 for a single `char` and 2 for `a*`.
+    pub fn depth(&self) -> usize {
+        // SAFETY: the same as for `node_count`
+        unsafe { Re::size_and_depth(self.tree) }.1
+    }
+
+    /// Hashes the structure of the tree: the kind of every node and the symbols it holds, but not
+    /// where it is allocated. `Regex`es that are `==` hash the same, even if one is a derivative
+    /// and the other was built from scratch.
+    pub fn canonical_hash<H: Hasher>(&self, state: &mut H) {
+        // SAFETY: the tree is owned by this `Regex` or one of its parents, which outlive it and
+        // can't be resized while it borrows them
+        unsafe { Re::hash_tree(self.tree, state) }
+    }
+
+    /// Copies `r` into `alloc`, bailing out if `alloc` runs out of space.
+    ///
+    /// ## Safety
+    /// `alloc` must not own `r`. `r` must be valid for reads and live for the duration of the
+    /// function.
+    unsafe fn rebuild_rec(
+        alloc: &mut VecAlloc<Re<A>>,
+        r: Const<Re<A>>,
+    ) -> Result<Const<Re<A>>, ()> {
+        let r = r.read();
+        match r {
+            Re::Zero | Re::One | Re::Char(_) | Re::Range(..) | Re::Any => try_alloc(alloc, r),
+            Re::AnyOf(set, len) => {
+                let set = alloc_set(alloc, any_of(set, len))?;
+                try_alloc(alloc, Re::AnyOf(set, len))
+            }
+            Re::NotAnyOf(set, len) => {
+                let set = alloc_set(alloc, any_of(set, len))?;
+                try_alloc(alloc, Re::NotAnyOf(set, len))
+            }
+            Re::Alt(r1, r2) => {
+                let r1 = Self::rebuild_rec(alloc, r1)?;
+                let r2 = Self::rebuild_rec(alloc, r2)?;
+                try_alloc(alloc, Re::Alt(r1, r2))
+            }
+            Re::Seq(r1, r2) => {
+                let r1 = Self::rebuild_rec(alloc, r1)?;
+                let r2 = Self::rebuild_rec(alloc, r2)?;
+                try_alloc(alloc, Re::Seq(r1, r2))
+            }
+            Re::And(r1, r2) => {
+                let r1 = Self::rebuild_rec(alloc, r1)?;
+                let r2 = Self::rebuild_rec(alloc, r2)?;
+                try_alloc(alloc, Re::And(r1, r2))
+            }
+            Re::Star(r) => {
+                let r = Self::rebuild_rec(alloc, r)?;
+                try_alloc(alloc, Re::Star(r))
+            }
+            Re::Plus(r) => {
+                let r = Self::rebuild_rec(alloc, r)?;
+                try_alloc(alloc, Re::Plus(r))
+            }
+            Re::Opt(r) => {
+                let r = Self::rebuild_rec(alloc, r)?;
+                try_alloc(alloc, Re::Opt(r))
+            }
+            Re::Not(r) => {
+                let r = Self::rebuild_rec(alloc, r)?;
+                try_alloc(alloc, Re::Not(r))
+            }
+            Re::Mark(id, r) => {
+                let r = Self::rebuild_rec(alloc, r)?;
+                try_alloc(alloc, Re::Mark(id, r))
             }
         }
+    }
 
-        match rebuild_with_rec(alloc, r, r) {
+    /// Copies `r` into `alloc`.
+    ///
+    /// ## Safety
+    /// `alloc` must not own `r`. `r` must be valid for reads and live for the duration of the
+    /// function.
+    unsafe fn rebuild_with(alloc: &mut VecAlloc<Re<A>>, r: Const<Re<A>>) -> Const<Re<A>> {
+        match Self::rebuild_rec(alloc, r) {
             Ok(r) => r,
             Err(_) => Self::rebuild_with(alloc.resized(), r),
         }
@@ -254,63 +1019,190 @@ impl<'a> Regex<'a> {
     /// Completely clone the regex, taking ownership of it. This clone, performs a recursive
     /// search of the actual tree. Cloning a `Regex<'static>` can be done with clone_static
     /// instead, which performs a copy of the internal buffer and is much faster.
-    pub fn clone(&self) -> Regex<'static> {
-        let mut alloc = VecAlloc::new(self.alloc.capacity());
+    ///
+    /// The clone is an independent owner: every node of its tree is copied into its own
+    /// allocator, so it can outlive `self` and any parents `self` borrows from.
+    #[allow(clippy::should_implement_trait)]
+    pub fn clone(&self) -> Regex<'static, A> {
+        // a child's buffer is empty, so start where resizing it would get to anyway
+        let mut alloc = VecAlloc::new(self.alloc.capacity().max(Self::DEFAULT_CAPACITY));
         let tree = unsafe { Self::rebuild_with(&mut alloc, self.tree) };
 
         Regex {
             tree,
             alloc,
+            normalizer: self.normalizer.clone(),
+            // same tree, so the same analyses
+            analyses: self.analyses.clone(),
+            boundaries: self.boundaries,
             phantom: PhantomData,
         }
     }
 
-    /// SAFETY:
-    /// - `tree` must be a pointer to the root of a *different* Regex, aka NOT owned by `alloc`.
-    /// - On the first recursive call to this function, `r` must be equal to `tree `
-    unsafe fn der_rec<'b>(
-        tree: Const<Re>,
-        alloc: &mut VecAlloc<Re>,
-        r: Const<Re>,
-        c: char,
-    ) -> Result<Const<Re>, ()> {
+    /// The same as `clone`, for when the point is to get rid of the lifetime, e.g. to keep a
+    /// derivative around after its parent is gone.
+    #[allow(clippy::should_implement_trait)]
+    pub fn to_owned(&self) -> Regex<'static, A> {
+        self.clone()
+    }
+
+    /// Moves the tree into an allocator that is just big enough for it, and returns how much
+    /// capacity that freed. Derivatives and simplification leave unreachable nodes behind, and
+    /// every resize doubles the capacity, so a `Regex` that is kept around for a long time can be
+    /// much bigger than it needs to be. Does nothing if the tree doesn't get any smaller, e.g. for
+    /// a `child`, which doesn't own anything.
+    pub fn shrink_to_fit(&mut self) -> usize {
+        // copying the tree doesn't preserve sharing, so its size has to be found by trying
+        let mut scratch = VecAlloc::new(self.node_count().max(1));
+        let tree = unsafe { Self::rebuild_with(&mut scratch, self.tree) };
+        if scratch.len() >= self.alloc.capacity() {
+            return 0;
+        }
+        let mut alloc = VecAlloc::new(scratch.len());
+        // SAFETY: `tree` is owned by `scratch`, which outlives this
+        self.tree = unsafe { Self::rebuild_with(&mut alloc, tree) };
+        let freed = self.alloc.capacity() - alloc.capacity();
+        self.alloc = alloc;
+        freed
+    }
+
+    /// ## Safety
+    /// `r` must be valid for reads. It may be owned by `alloc` itself, since allocating never moves
+    /// the nodes already there, but then `alloc` must not be resized while `r` is still in use.
+    unsafe fn der_rec(
+        alloc: &mut VecAlloc<Re<A>>,
+        r: Const<Re<A>>,
+        c: A::Symbol,
+    ) -> Result<Const<Re<A>>, ()> {
         match r.as_ref() {
             Re::Zero => Ok(r),
             Re::One => try_alloc(alloc, Re::Zero),
-            Re::Char(d) => try_alloc(alloc, if c == *d { Re::One } else { Re::Zero }),
+            Re::Any => try_alloc(alloc, Re::One),
+            Re::AnyOf(set, len) => try_alloc(
+                alloc,
+                if any_of_contains(*set, *len, c) {
+                    Re::One
+                } else {
+                    Re::Zero
+                },
+            ),
+            Re::NotAnyOf(set, len) => try_alloc(
+                alloc,
+                if any_of_contains(*set, *len, c) {
+                    Re::Zero
+                } else {
+                    Re::One
+                },
+            ),
+            Re::Char(d) => try_alloc(alloc, if A::matches(d, c) { Re::One } else { Re::Zero }),
+            Re::Range(lo, hi) => try_alloc(
+                alloc,
+                if A::in_range(lo, hi, c) {
+                    Re::One
+                } else {
+                    Re::Zero
+                },
+            ),
             Re::Alt(r1, r2) => {
-                let r = Re::Alt(
-                    Self::der_rec(tree, alloc, *r1, c)?,
-                    Self::der_rec(tree, alloc, *r2, c)?,
-                );
+                let r = Re::Alt(Self::der_rec(alloc, *r1, c)?, Self::der_rec(alloc, *r2, c)?);
                 try_alloc(alloc, r)
             }
             Re::Seq(r1, r2) => {
                 let r = if r1.as_ref().nullable() {
                     // der(r1).r2 | der(r2)
-                    let tmp = Re::Seq(Self::der_rec(tree, alloc, *r1, c)?, *r2);
-                    Re::Alt(try_alloc(alloc, tmp)?, Self::der_rec(tree, alloc, *r2, c)?)
+                    let tmp = Re::Seq(Self::der_rec(alloc, *r1, c)?, *r2);
+                    Re::Alt(try_alloc(alloc, tmp)?, Self::der_rec(alloc, *r2, c)?)
                 } else {
-                    Re::Seq(Self::der_rec(tree, alloc, *r1, c)?, *r2)
+                    Re::Seq(Self::der_rec(alloc, *r1, c)?, *r2)
                 };
                 try_alloc(alloc, r)
             }
+            Re::And(r1, r2) => {
+                let r = Re::And(Self::der_rec(alloc, *r1, c)?, Self::der_rec(alloc, *r2, c)?);
+                try_alloc(alloc, r)
+            }
             Re::Star(r1) => {
-                let r = Re::Seq(Self::der_rec(tree, alloc, *r1, c)?, r);
+                let r = Re::Seq(Self::der_rec(alloc, *r1, c)?, r);
+                try_alloc(alloc, r)
+            }
+            Re::Plus(r1) => {
+                // der(r+) = der(r).r*
+                let star = try_alloc(alloc, Re::Star(*r1))?;
+                let r = Re::Seq(Self::der_rec(alloc, *r1, c)?, star);
+                try_alloc(alloc, r)
+            }
+            // der(r?) = der(r|1) = der(r)|0 = der(r)
+            Re::Opt(r1) => Self::der_rec(alloc, *r1, c),
+            // only the start of a mark is tracked, see `Regex::captures`
+            Re::Mark(_, r1) => Self::der_rec(alloc, *r1, c),
+            Re::Not(r1) => {
+                let r = Re::Not(Self::der_rec(alloc, *r1, c)?);
                 try_alloc(alloc, r)
             }
         }
     }
 
-    // Produce the 'derivative' of this regex. The derivative is returned as a 'child', which means
-    // that it uses parts of `self` internally to reduce the need for some allocations and
-    // hopefully result in less `realloc`s on the internal buffer.
-    pub fn der<'b>(&'b self, c: char) -> Regex<'b> {
-        let mut alloc = VecAlloc::new(Self::DEFAULT_CAPACITY);
+    /// An upper bound on how many nodes `der_rec` allocates for `r`: it allocates at most two
+    /// nodes for every node it visits, so this counts the nodes it would visit. That takes a walk
+    /// over (about) the same nodes, but it is much cheaper than running out of space halfway and
+    /// restarting, and it never allocates.
+    ///
+    /// ## Safety
+    /// `r` must be valid for reads.
+    unsafe fn der_size_hint(r: Const<Re<A>>) -> usize {
+        let mut visited = 0;
+        let mut stack = vec![r];
+        while let Some(r) = stack.pop() {
+            visited += 1;
+            match r.as_ref() {
+                Re::Alt(r1, r2) | Re::And(r1, r2) => {
+                    stack.push(*r1);
+                    stack.push(*r2);
+                }
+                Re::Seq(r1, r2) => {
+                    stack.push(*r1);
+                    if r1.as_ref().nullable() {
+                        stack.push(*r2);
+                    }
+                }
+                Re::Star(r1) | Re::Plus(r1) | Re::Opt(r1) | Re::Not(r1) | Re::Mark(_, r1) => {
+                    stack.push(*r1)
+                }
+                _ => {}
+            }
+        }
+        2 * visited
+    }
+
+    /// Produce the 'derivative' of this regex. The derivative is returned as a 'child', which means
+    /// that it uses parts of `self` internally to reduce the need for some allocations and
+    /// hopefully result in less `realloc`s on the internal buffer.
+    ///
+    /// In particular, deriving a `Star` points back at the `Star` node in `self`, so the
+    /// derivative borrows `self` and can't outlive it. `clone` it to keep it around for longer.
+    ///
+    /// ```compile_fail
+    /// use fast_regex::regex::{build_plan::ImplicitRe, Regex};
+    ///
+    /// let d = {
+    ///     let r = Regex::from(&'a'.star());
+    ///     r.der('a')
+    /// };
+    /// d.nullable();
+    /// ```
+    pub fn der<'b>(&'b self, c: A::Symbol) -> Regex<'b, A> {
+        // an empty buffer doesn't allocate, so this only allocates once
+        let mut alloc = VecAlloc::new(0);
+        // SAFETY: the tree is owned by this `Regex` or its parents
+        alloc.reserve(unsafe { Self::der_size_hint(self.tree) }.max(Self::DEFAULT_CAPACITY));
         let tree = loop {
-            match unsafe { Self::der_rec(self.tree, &mut alloc, self.tree, c) } {
+            match unsafe { Self::der_rec(&mut alloc, self.tree, c) } {
                 Ok(tree) => break tree,
-                Err(_) => alloc.resize(),
+                Err(_) => {
+                    #[cfg(test)]
+                    RESTARTS.with(|n| n.set(n.get() + 1));
+                    alloc.resize()
+                }
             }
         };
 
@@ -318,15 +1210,40 @@ impl<'a> Regex<'a> {
             // SAFETY: `tree` is a valid pointer into `alloc` which we take ownership of.
             tree,
             alloc,
-            phantom: PhantomData::default(),
+            normalizer: self.normalizer.clone(),
+            analyses: OnceLock::new(),
+            boundaries: Boundaries::default(),
+            phantom: PhantomData,
         }
     }
 
-    unsafe fn simp_rec(
-        tree: Const<Re>,
-        alloc: &mut VecAlloc<Re>,
-        r: Const<Re>,
-    ) -> Result<Const<Re>, ()> {
+    /// Like `der`, but allocates the derivative into `out` instead of a new allocator, and returns
+    /// its root. Meant to be used with an `out` that the caller `clear`s between uses, so that the
+    /// same buffer can be reused over and over.
+    ///
+    /// The returned tree may point into `self`'s tree as well as into `out`, so it is only valid
+    /// for as long as `self` is alive and `out` is not cleared, resized or dropped. If `out` is
+    /// too small it grows first, invalidating anything else that was allocated in it, so a buffer
+    /// that is reused soon stops growing.
+    pub fn der_into(&self, c: A::Symbol, out: &mut VecAlloc<Re<A>>) -> Const<Re<A>> {
+        // SAFETY: the tree is owned by this `Regex` or its parents
+        out.reserve(unsafe { Self::der_size_hint(self.tree) });
+        loop {
+            // SAFETY: `self.tree` is owned by `self` or its parents, never by `out`
+            match unsafe { Self::der_rec(out, self.tree, c) } {
+                Ok(tree) => break tree,
+                Err(_) => out.resize(),
+            }
+        }
+    }
+
+    /// Simplifies `r`, only allocating the nodes that change.
+    ///
+    /// `Alt`s come out in a canonical form, so that alternations that only differ in how they are
+    /// nested end up equal: a right-leaning spine `a|(b|(c|...))` whose branches aren't `Alt`s,
+    /// `Zero` or duplicates of each other. Branches keep their order, and when a branch appears
+    /// more than once, only its last occurrence is kept.
+    unsafe fn simp_rec(alloc: &mut VecAlloc<Re<A>>, r: Const<Re<A>>) -> Result<Const<Re<A>>, ()> {
         // This is a little tough to understand why we only need to allocate so rarely.
         // Consider something like this:
         //
@@ -352,33 +1269,92 @@ impl<'a> Regex<'a> {
         // - sub nodes change during simplification
         // - new nodes are created (e.g. converting from one node type to another)
         match r.as_ref() {
-            Re::Alt(r1s, r2s) => unsafe {
-                let r1 = Self::simp_rec(tree, alloc, *r1s)?;
-                let r2 = Self::simp_rec(tree, alloc, *r2s)?;
-                match (r1.as_ref(), r2.as_ref()) {
-                    (Re::Zero, _) => Ok(r2),
-                    (_, Re::Zero) => Ok(r1),
-                    (r1a, r2a) => {
-                        if r1a.eq(&r2a) {
-                            Ok(r1)
-                        } else {
-                            if Re::const_eq(r1, *r1s) && Re::const_eq(r2, *r2s) {
-                                Ok(r)
-                            } else {
-                                try_alloc(alloc, Re::Alt(r1, r2))
-                            }
+            Re::Alt(..) => unsafe {
+                // The whole spine is simplified at once, rather than one `Alt` at a time, so that
+                // a wide alternation isn't scanned for duplicates again at every level.
+                // `nodes[i]` is the part of the spine that starts with `branches[i]`.
+                let mut nodes = Vec::new();
+                let mut branches = Vec::new();
+                let mut next = r;
+                loop {
+                    nodes.push(next);
+                    match next.read() {
+                        Re::Alt(r1, r2) => {
+                            branches.push(r1);
+                            next = r2;
+                        }
+                        _ => {
+                            branches.push(next);
+                            break;
                         }
                     }
                 }
+
+                let mut simplified = Vec::with_capacity(branches.len());
+                let mut zero = None;
+                for &b in &branches {
+                    let b = Self::simp_rec(alloc, b)?;
+                    match b.as_ref() {
+                        Re::Zero => zero = Some(b),
+                        // a canonical spine already, so its branches can go straight in
+                        Re::Alt(..) => simplified.extend(alt_spine(b)),
+                        _ => simplified.push(b),
+                    }
+                }
+
+                // Keep the last occurrence of every branch. `kept` is back to front.
+                let mut seen: HashMap<u64, Vec<Const<Re<A>>>> = HashMap::new();
+                let mut kept = Vec::with_capacity(simplified.len());
+                for &b in simplified.iter().rev() {
+                    let mut hasher = DefaultHasher::new();
+                    Re::hash_tree(b, &mut hasher);
+                    let same_hash = seen.entry(hasher.finish()).or_default();
+                    if !same_hash.iter().any(|&c| Re::const_eq(c, b)) {
+                        same_hash.push(b);
+                        kept.push(b);
+                    }
+                }
+                if kept.is_empty() {
+                    return Ok(zero.expect("every branch is `Zero`"));
+                }
+
+                // Whatever is left of the end of the old spine can be reused as-is
+                let reused = (kept.iter())
+                    .zip(branches.iter().rev())
+                    .take_while(|(k, b)| k.ptr_eq(**b))
+                    .count();
+                let (mut spine, rest) = match reused {
+                    0 => (kept[0], &kept[1..]),
+                    n => (nodes[branches.len() - n], &kept[n..]),
+                };
+                for &b in rest {
+                    spine = try_alloc(alloc, Re::Alt(b, spine))?;
+                }
+                Ok(spine)
             },
             Re::Seq(r1s, r2s) => unsafe {
-                let r1 = Self::simp_rec(tree, alloc, *r1s)?;
-                let r2 = Self::simp_rec(tree, alloc, *r2s)?;
+                let r1 = Self::simp_rec(alloc, *r1s)?;
+                let r2 = Self::simp_rec(alloc, *r2s)?;
                 match (r1.as_ref(), r2.as_ref()) {
                     (Re::Zero, _) => Ok(r1),
                     (_, Re::Zero) => Ok(r2),
                     (Re::One, _) => Ok(r2),
                     (_, Re::One) => Ok(r1),
+                    // r*.r* == r*
+                    (Re::Star(s1), Re::Star(s2)) if Re::const_eq(*s1, *s2) => Ok(r1),
+                    // r*.(r*.s) == r*.s
+                    (Re::Star(s1), Re::Seq(r21, _)) if is_star_of(*r21, *s1) => Ok(r2),
+                    // (r.s).t == r.(s.t), so that equal concatenations have the same shape. Like
+                    // for `Alt`, `r2` is a spine already, and the parts of `r1` go in front of it.
+                    // A `Seq` that was already there is a spine too, so only a new one needs it.
+                    (Re::Seq(..), _) if !Re::const_eq(r1, *r1s) => {
+                        let mut spine = r2;
+                        let parts: Vec<_> = seq_spine(r1).collect();
+                        for p in parts.into_iter().rev() {
+                            spine = try_alloc(alloc, Re::Seq(p, spine))?;
+                        }
+                        Ok(spine)
+                    }
                     _ => {
                         if Re::const_eq(r1, *r1s) && Re::const_eq(r2, *r2s) {
                             Ok(r)
@@ -388,16 +1364,78 @@ impl<'a> Regex<'a> {
                     }
                 }
             },
+            Re::And(r1s, r2s) => unsafe {
+                let r1 = Self::simp_rec(alloc, *r1s)?;
+                let r2 = Self::simp_rec(alloc, *r2s)?;
+                match (r1.as_ref(), r2.as_ref()) {
+                    (Re::Zero, _) => Ok(r1),
+                    (_, Re::Zero) => Ok(r2),
+                    // r&r == r
+                    (r1a, r2a) if r1a.eq(r2a) => Ok(r1),
+                    // r&!r == !r&r == 0
+                    (Re::Not(r2n), _) if Re::const_eq(*r2n, r2) => try_alloc(alloc, Re::Zero),
+                    (_, Re::Not(r1n)) if Re::const_eq(*r1n, r1) => try_alloc(alloc, Re::Zero),
+                    _ if Re::const_eq(r1, *r1s) && Re::const_eq(r2, *r2s) => Ok(r),
+                    _ => try_alloc(alloc, Re::And(r1, r2)),
+                }
+            },
+            Re::Star(r1s) => unsafe {
+                let r1 = Self::simp_rec(alloc, *r1s)?;
+                match r1.as_ref() {
+                    // 0* == 1* == 1
+                    Re::Zero | Re::One => try_alloc(alloc, Re::One),
+                    // (r*)* == r*
+                    Re::Star(_) => Ok(r1),
+                    _ if Re::const_eq(r1, *r1s) => Ok(r),
+                    _ => try_alloc(alloc, Re::Star(r1)),
+                }
+            },
+            Re::Opt(r1s) => unsafe {
+                let r1 = Self::simp_rec(alloc, *r1s)?;
+                match r1.as_ref() {
+                    // r? == r when r already matches the empty string
+                    r1a if r1a.nullable() => Ok(r1),
+                    Re::Zero => try_alloc(alloc, Re::One),
+                    _ if Re::const_eq(r1, *r1s) => Ok(r),
+                    _ => try_alloc(alloc, Re::Opt(r1)),
+                }
+            },
+            Re::Not(r1s) => unsafe {
+                let r1 = Self::simp_rec(alloc, *r1s)?;
+                match r1.as_ref() {
+                    // !!r == r
+                    Re::Not(r11) => Ok(*r11),
+                    _ if Re::const_eq(r1, *r1s) => Ok(r),
+                    _ => try_alloc(alloc, Re::Not(r1)),
+                }
+            },
+            Re::Mark(id, r1s) => unsafe {
+                let r1 = Self::simp_rec(alloc, *r1s)?;
+                match r1.as_ref() {
+                    // nothing can start a mark that never matches
+                    Re::Zero => Ok(r1),
+                    _ if Re::const_eq(r1, *r1s) => Ok(r),
+                    _ => try_alloc(alloc, Re::Mark(*id, r1)),
+                }
+            },
             _ => Ok(r),
         }
     }
 
-    pub fn simp<'b>(&'b self) -> Regex<'b> {
-        let mut alloc = VecAlloc::new(Self::DEFAULT_CAPACITY);
+    pub fn simp<'b>(&'b self) -> Regex<'b, A> {
+        // Only nodes that change are allocated, and those are mostly the ones the last `der` made,
+        // so the size of our own allocator is a good guess. It is only a guess though, `Alt`s and
+        // `Seq`s can take more to put back into canonical form, so leave room for that.
+        let mut alloc = VecAlloc::new(0);
+        alloc.reserve((2 * self.alloc.len()).max(Self::DEFAULT_CAPACITY));
         let tree = loop {
-            match unsafe { Self::simp_rec(self.tree, &mut alloc, self.tree) } {
+            match unsafe { Self::simp_rec(&mut alloc, self.tree) } {
                 Ok(tree) => break tree,
-                Err(_) => alloc.resize(),
+                Err(_) => {
+                    #[cfg(test)]
+                    RESTARTS.with(|n| n.set(n.get() + 1));
+                    alloc.resize()
+                }
             }
         };
 
@@ -405,40 +1443,582 @@ impl<'a> Regex<'a> {
             // SAFETY: `tree` is a valid pointer into `alloc` which we take ownership of.
             tree,
             alloc,
-            phantom: PhantomData::default(),
+            normalizer: self.normalizer.clone(),
+            analyses: OnceLock::new(),
+            boundaries: Boundaries::default(),
+            phantom: PhantomData,
         }
     }
 
-    fn ders(r: Regex<'static>, cs: &[char]) -> Regex<'static> {
+    /// Like `simp`, but simplifies again and again until the tree stops changing (or a few dozen
+    /// passes have been made). One pass is enough for most trees, so `simp` is what the matching
+    /// loop uses. This is for when the smallest form matters more than the time it takes.
+    pub fn simp_full(&self) -> Regex<'static, A> {
+        let mut r = self.simp().clone();
+        for _ in 1..Self::SIMP_FIXPOINT_CAP {
+            let next = r.simp().clone();
+            if next == r {
+                break;
+            }
+            r = next;
+        }
+        r
+    }
+
+    /// Whether no more input can change if this matches, because the state is dead (`Zero`) or
+    /// universal (see `Re::is_universal`).
+    fn is_settled(&self) -> bool {
         // SAFETY: dereferencing a reference to immutable buffers
-        if let Re::Zero = unsafe { r.tree.as_ref() } {
-            return r;
+        let tree = unsafe { self.tree.as_ref() };
+        matches!(tree, Re::Zero) || tree.is_universal()
+    }
+
+    /// Derives `r` by every char in `cs`. Stops early, after any char, once the state
+    /// `is_settled`. In that case the state is returned as-is.
+    fn ders(
+        mut r: Regex<'static, A>,
+        mut cs: impl Iterator<Item = A::Symbol>,
+    ) -> Regex<'static, A> {
+        // We pull four chars at a time, so that the intermediate derivatives can borrow from each
+        // other instead of being cloned.
+        let mut chunk = Vec::with_capacity(4);
+        loop {
+            if r.is_settled() {
+                return r;
+            }
+            chunk.clear();
+            chunk.extend(cs.by_ref().take(4));
+            r = match chunk[..] {
+                [] => return r,
+                [c1, c2, c3, c4] => {
+                    let c1 = r.der(c1);
+                    let c1s = c1.simp();
+                    if c1s.is_settled() {
+                        return c1s.clone();
+                    }
+                    let c2 = c1s.der(c2);
+                    let c2s = c2.simp();
+                    if c2s.is_settled() {
+                        return c2s.clone();
+                    }
+                    let c3 = c2s.der(c3);
+                    let c3s = c3.simp();
+                    if c3s.is_settled() {
+                        return c3s.clone();
+                    }
+                    let c4 = c3s.der(c4);
+                    let c4s = c4.simp();
+                    c4s.clone()
+                }
+                ref cs => {
+                    for c in cs {
+                        r = r.der(*c).simp().clone();
+                        if r.is_settled() {
+                            return r;
+                        }
+                    }
+                    r
+                }
+            };
         }
-        match cs {
-            [] => r,
-            [c1, c2, c3, c4, cs @ ..] => {
-                let c1 = r.der(*c1);
-                let c1s = c1.simp();
-                let c2 = c1s.der(*c2);
-                let c2s = c2.simp();
-                let c3 = c2s.der(*c3);
-                let c3s = c3.simp();
-                let c4 = c3s.der(*c4);
-                let c4s = c4.simp();
-                Regex::ders(c4s.clone(), cs)
+    }
+
+    /// Derives by every symbol in `cs` in turn, like repeated `der(c).simp()`, but with a single
+    /// allocator for every step instead of a new one per step. Each step is derived and
+    /// simplified in place, and only when the allocator fills up is the current state copied
+    /// into a fresh one, leaving the dead intermediate states behind.
+    pub fn der_many(&self, cs: &[A::Symbol]) -> Regex<'static, A> {
+        let mut alloc = VecAlloc::new(Self::DEFAULT_CAPACITY);
+        // points into `self` or `alloc`
+        let mut state = self.tree;
+        for &c in cs {
+            // SAFETY: `state` is owned by `self` or `alloc`, and `alloc` is only replaced once
+            // `state` has been copied out of it
+            let mut compacted = false;
+            state = loop {
+                let next = unsafe {
+                    Self::der_rec(&mut alloc, state, c).and_then(|d| Self::simp_rec(&mut alloc, d))
+                };
+                match next {
+                    Ok(next) => break next,
+                    Err(_) => {
+                        // failing again straight after compacting means that this step needs
+                        // more room than there is, so grow
+                        let capacity = alloc.capacity() * if compacted { 2 } else { 1 };
+                        compacted = true;
+                        let mut fresh = VecAlloc::new(capacity);
+                        state = unsafe { Self::rebuild_with(&mut fresh, state) };
+                        alloc = fresh;
+                    }
+                }
+            };
+            // SAFETY: as above
+            if let Re::Zero = unsafe { state.as_ref() } {
+                break;
             }
-            [c, cs @ ..] => Regex::ders(r.der(*c).simp().clone(), cs),
+        }
+
+        // SAFETY: `state` may still point into `self`, which is fine for as long as we borrow it,
+        // and `clone` copies it out
+        let borrowed: Regex<'_, A> = unsafe { Regex::new(state, alloc) };
+        Regex {
+            normalizer: self.normalizer.clone(),
+            ..borrowed.clone()
         }
     }
 
-    pub fn is_match(&self, s: &str) -> bool {
-        let d = Regex::ders(self.clone(), &s.chars().collect::<Vec<char>>());
+    /// Like `is_match`, but for any `Alphabet`: checks whether this `Regex` accepts exactly the
+    /// symbols of `s`.
+    pub fn is_match_symbols(&self, s: impl IntoIterator<Item = A::Symbol>) -> bool {
+        let mut s = s.into_iter().peekable();
+        // nothing to derive by, so don't copy the tree
+        if s.peek().is_none() {
+            return self.nullable();
+        }
+        let d = Self::ders(self.clone(), s.map(|c| self.normalize(c)));
         d.nullable()
     }
 }
 
-impl Regex<'static> {
+impl<'a> Regex<'a> {
+    /// Maps every `char` of the input through `f` before matching it, e.g. to treat `'-'` and `'_'`
+    /// the same. The pattern itself is left as-is, so its `char`s should already be normalized.
+    /// The normalizer is shared with every `Regex` derived from this one.
+    ///
+    /// Only the matching methods (`is_match`, `longest_prefix`, `contains` and friends) use the
+    /// normalizer. `der` and the other methods that take a single `char` do not.
+    pub fn with_char_normalizer(
+        mut self,
+        f: impl Fn(char) -> char + Send + Sync + 'static,
+    ) -> Self {
+        self.normalizer = Some(Arc::new(f));
+        self
+    }
+
+    /// Requires every match to start and end at the given `boundaries`. These are checked by the
+    /// matching methods (`is_match`, `longest_prefix`, `contains` and friends), and are not carried
+    /// over to derivatives.
+    pub fn with_boundaries(mut self, boundaries: Boundaries) -> Self {
+        self.boundaries = boundaries;
+        self
+    }
+
+    /// Checks whether this `Regex` matches all of `s`, from its first `char` to its last, as if
+    /// it was anchored at both ends. Same as `is_full_match`.
+    ///
+    /// To check whether it matches somewhere in `s`, see `is_partial_match`.
+    pub fn is_match(&self, s: &str) -> bool {
+        self.is_full_match(s)
+    }
+
+    /// Like `is_match`, but also returns how many derivatives were taken, i.e. how many `char`s
+    /// were consumed before the state settled (see `der_str`). Unlike the time it takes, this
+    /// doesn't depend on the machine, so it makes a stable measure of how well `simp` keeps
+    /// states from growing. The cheap rejections `is_match` makes first are skipped, so that the
+    /// count doesn't depend on them.
+    pub fn is_match_counting(&self, s: &str) -> (bool, usize) {
+        if !Boundaries::check(self.boundaries.start, s, 0)
+            || !Boundaries::check(self.boundaries.end, s, s.len())
+        {
+            return (false, 0);
+        }
+        let mut steps = 0;
+        let mut r = self.clone();
+        for c in s.chars() {
+            if r.is_settled() {
+                break;
+            }
+            r = r.der(self.normalize(c)).simp().clone();
+            steps += 1;
+        }
+        (r.nullable(), steps)
+    }
+
+    /// Checks whether this `Regex` matches all of `s`, from its first `char` to its last. `abc`
+    /// full matches `"abc"`, but not `"xabcx"`.
+    pub fn is_full_match(&self, s: &str) -> bool {
+        // nothing to derive by, so don't copy the tree or compute the analyses
+        if s.is_empty() {
+            return self.is_match_iter(s.chars());
+        }
+
+        // Cheap rejections first, so that we only derive when we have to
+        let analyses = self.analyses();
+        let Some((min, max)) = analyses.length_bounds else {
+            return false;
+        };
+        let len = s.chars().count();
+        if len < min || max.is_some_and(|max| len > max) {
+            return false;
+        }
+        let mut cs = s.chars().map(|c| self.normalize(c));
+        if !analyses
+            .required_prefix
+            .iter()
+            .all(|p| cs.next() == Some(*p))
+        {
+            return false;
+        }
+
+        self.is_match_iter(s.chars())
+    }
+
+    /// Like `is_match`, for input that is already split into `char`s.
+    pub fn is_match_chars(&self, chars: &[char]) -> bool {
+        self.is_match_iter(chars.iter().copied())
+    }
+
+    /// Like `is_match`, for input that is produced lazily. Stops pulling `char`s as soon as the
+    /// state can't match anything, so a mismatch near the start is cheap even if `chars` is long.
+    /// The end boundary, if there is one, still needs the last `char`, so `chars` is drained in
+    /// that case.
+    pub fn is_match_iter(&self, chars: impl IntoIterator<Item = char>) -> bool {
+        let mut chars = chars.into_iter().peekable();
+        let first = chars.peek().copied();
+        if !self.boundaries.start.is_none_or(|b| b.holds(None, first)) {
+            return false;
+        }
+        let mut last = None;
+        let d = Self::ders(
+            self.clone(),
+            (chars.by_ref())
+                .inspect(|&c| last = Some(c))
+                .map(|c| self.normalize(c)),
+        );
+        if !d.nullable() {
+            return false;
+        }
+        // a universal state stops early too, so the last `char` may not have been seen yet
+        (self.boundaries.end).is_none_or(|b| b.holds(chars.last().or(last), None))
+    }
+
+    /// Derives by every `char` of `s` in turn, simplifying as it goes, and returns the final state.
+    /// Like `is_match`, this maps each `char` through the normalizer first.
+    ///
+    /// The result is what is left of the pattern once `s` has been consumed: it matches `t`
+    /// exactly when `self` matches `s` followed by `t`. `is_match` is this followed by `nullable`.
+    /// Once the state is dead or matches everything, the rest of `s` is skipped.
+    ///
+    /// ```
+    /// use fast_regex::regex::{build_plan::ImplicitRe, Regex};
+    ///
+    /// let r = Regex::from(&"ab".re().alt("ac").seq('d'.star()));
+    /// let rest = r.der_str("a");
+    /// assert!(!rest.nullable());
+    /// assert!(rest.is_match("bdd"));
+    /// assert!(rest.is_match("c"));
+    /// assert!(!rest.is_match("abd"));
+    /// ```
+    pub fn der_str(&self, s: &str) -> Regex<'static> {
+        Self::ders(self.clone(), s.chars().map(|c| self.normalize(c)))
+    }
+
+    /// Finds the longest prefix of `s` that this `Regex` accepts, returning its length in bytes.
+    /// Unlike `is_match`, the rest of `s` is allowed to be left over. Returns `Some(0)` if only the
+    /// empty prefix matches and `None` if no prefix matches at all.
+    ///
+    /// This derives one `char` at a time and remembers the last point where the state was
+    /// `nullable`, stopping early once it is dead. Taking the longest match like this is what a
+    /// tokenizer wants:
+    ///
+    /// ```
+    /// use fast_regex::regex::{build_plan::{ImplicitRe, Re}, Regex};
+    ///
+    /// let digits = || Re::range('0', '9').plus();
+    /// let number = Regex::from(&digits().seq('.'.seq(digits()).opt()));
+    /// assert_eq!(number.longest_prefix("3.14+2"), Some(4));
+    /// // a trailing `.` isn't part of the number
+    /// assert_eq!(number.longest_prefix("3.+2"), Some(1));
+    /// assert_eq!(number.longest_prefix("+2"), None);
+    /// ```
+    pub fn longest_prefix(&self, s: &str) -> Option<usize> {
+        self.longest_at(s, 0)
+    }
+
+    /// The end of the longest match that starts at byte `start` of `s`, as a byte offset into `s`.
+    fn longest_at(&self, s: &str, start: usize) -> Option<usize> {
+        if !Boundaries::check(self.boundaries.start, s, start) {
+            return None;
+        }
+        let ends_here = |r: &Regex, i| r.nullable() && Boundaries::check(self.boundaries.end, s, i);
+        let mut longest = if ends_here(self, start) {
+            Some(start)
+        } else {
+            None
+        };
+        let mut r = self.clone();
+        for (i, c) in s[start..].char_indices() {
+            r = r.der(self.normalize(c)).simp().clone();
+            // SAFETY: dereferencing a reference to immutable buffers
+            if let Re::Zero = unsafe { r.tree.as_ref() } {
+                break;
+            }
+            let end = start + i + c.len_utf8();
+            if ends_here(&r, end) {
+                longest = Some(end);
+            }
+        }
+        longest
+    }
+
+    /// Checks whether a match starts at byte `start` of `s`, stopping as soon as one is found.
+    fn matches_at(&self, s: &str, start: usize) -> bool {
+        if !Boundaries::check(self.boundaries.start, s, start) {
+            return false;
+        }
+        let ends_here = |r: &Regex, i| r.nullable() && Boundaries::check(self.boundaries.end, s, i);
+        if ends_here(self, start) {
+            return true;
+        }
+        let mut r = self.clone();
+        for (i, c) in s[start..].char_indices() {
+            r = r.der(self.normalize(c)).simp().clone();
+            // SAFETY: dereferencing a reference to immutable buffers
+            if let Re::Zero = unsafe { r.tree.as_ref() } {
+                return false;
+            }
+            if ends_here(&r, start + i + c.len_utf8()) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Checks whether this `Regex` matches some substring of `s` (possibly the empty one, or all
+    /// of `s`), as if it wasn't anchored at either end. `abc` partially matches both `"abc"` and
+    /// `"xabcx"`. Same as `contains`.
+    ///
+    /// Every start position is tried in turn, like `find` does, rather than deriving `.*r.*`, so
+    /// the states never have to carry every partial match at once.
+    pub fn is_partial_match(&self, s: &str) -> bool {
+        self.contains(s)
+    }
+
+    /// Checks whether this `Regex` matches anywhere in `s`, instead of matching all of `s` like
+    /// `is_match` does. See `is_partial_match`.
+    pub fn contains(&self, s: &str) -> bool {
+        Self::starts(s).any(|i| self.starts_match(s, i))
+    }
+
+    /// The byte offset of every position in `s` that a match could start at, including the end.
+    fn starts(s: &str) -> impl Iterator<Item = usize> + '_ {
+        s.char_indices().map(|(i, _)| i).chain([s.len()])
+    }
+
+    /// A cheap check for whether a match could start at byte `i` of `s`. A non-empty match has to
+    /// start with something in the `first_set`.
+    fn could_start(&self, s: &str, i: usize) -> bool {
+        self.nullable()
+            || s[i..].chars().next().is_some_and(|c| {
+                self.first_set()
+                    .is_none_or(|first_set| first_set.contains(&self.normalize(c)))
+            })
+    }
+
+    /// Checks whether a match starts at byte `i` of `s`.
+    fn starts_match(&self, s: &str, i: usize) -> bool {
+        self.could_start(s, i) && self.matches_at(s, i)
+    }
+
+    /// Finds the leftmost match in `s`, and the longest one of those, as a range of byte offsets
+    /// into `s`.
+    ///
+    /// Each start position is tried in turn, deriving until the state dies or `s` runs out, so
+    /// this takes O(n·m) derivatives in the worst case, for `n` chars in `s` and `m` the length of
+    /// the longest (partial) match. Each derivative costs time in the size of the state, on top.
+    pub fn find(&self, s: &str) -> Option<(usize, usize)> {
+        self.find_from(s, 0)
+    }
+
+    /// Yields every match in `s` that `find` would, left to right, without overlaps. Each search
+    /// resumes where the last match ended. After an empty match it resumes one `char` later
+    /// instead, so that it doesn't find the same empty match forever.
+    pub fn find_iter<'s>(&'s self, s: &'s str) -> impl Iterator<Item = (usize, usize)> + 's {
+        let mut from = Some(0);
+        std::iter::from_fn(move || {
+            let (start, end) = self.find_from(s, from?)?;
+            from = if start < end {
+                Some(end)
+            } else {
+                s[end..].chars().next().map(|c| end + c.len_utf8())
+            };
+            Some((start, end))
+        })
+    }
+
+    /// Yields the pieces of `s` between the matches that `find_iter` finds, like `str::split`
+    /// with this `Regex` as the delimiter. A match at the very start or end of `s` gives an empty
+    /// first or last piece, and so do two matches in a row. Empty matches split too, so a
+    /// `Regex` that only matches the empty string splits `"ab"` into `""`, `"a"`, `"b"` and `""`.
+    pub fn split<'s, 't: 's>(&'s self, s: &'t str) -> impl Iterator<Item = &'t str> + 's {
+        let mut matches = self.find_iter(s);
+        let mut last = Some(0);
+        std::iter::from_fn(move || {
+            let start = last?;
+            match matches.next() {
+                Some((end, next)) => {
+                    last = Some(next);
+                    Some(&s[start..end])
+                }
+                None => {
+                    last = None;
+                    Some(&s[start..])
+                }
+            }
+        })
+    }
+
+    /// Replaces the first match that `find` finds with `replacement`, which is taken literally.
+    pub fn replace(&self, haystack: &str, replacement: &str) -> String {
+        self.replace_matches(haystack, replacement, self.find(haystack))
+    }
+
+    /// Replaces every match that `find_iter` finds with `replacement`, which is taken literally.
+    /// Empty matches are replaced too, including one right after a non-empty match, e.g. replacing
+    /// `a*` with `-` in `"bab"` gives `"-b--b-"`.
+    pub fn replace_all(&self, haystack: &str, replacement: &str) -> String {
+        self.replace_matches(haystack, replacement, self.find_iter(haystack))
+    }
+
+    fn replace_matches(
+        &self,
+        haystack: &str,
+        replacement: &str,
+        matches: impl IntoIterator<Item = (usize, usize)>,
+    ) -> String {
+        let mut replaced = String::with_capacity(haystack.len());
+        let mut last = 0;
+        for (start, end) in matches {
+            replaced.push_str(&haystack[last..start]);
+            replaced.push_str(replacement);
+            last = end;
+        }
+        replaced.push_str(&haystack[last..]);
+        replaced
+    }
+
+    /// Like `find`, but only considers matches that start at byte `from` or later.
+    fn find_from(&self, s: &str, from: usize) -> Option<(usize, usize)> {
+        Self::starts(&s[from..])
+            .map(|i| from + i)
+            .filter(|&i| self.could_start(s, i))
+            .find_map(|i| self.longest_at(s, i).map(|end| (i, end)))
+    }
+
+    /// For every char position in `s`, including the end, whether a match starts there.
+    pub fn match_start_bitset(&self, s: &str) -> Vec<bool> {
+        Self::starts(s).map(|i| self.starts_match(s, i)).collect()
+    }
+
+    /// Yields the lines of `text` (as split by `str::lines`) that this `Regex` matches anywhere
+    /// in, like grep does.
+    pub fn matching_lines<'s, 't: 's>(
+        &'s self,
+        text: &'t str,
+    ) -> impl Iterator<Item = &'t str> + 's {
+        text.lines().filter(|line| self.contains(line))
+    }
+
+    /// A `Regex` for the reversed language: it matches `s` exactly when this one matches `s`
+    /// backwards. Every `Seq` has its two sides swapped, and the start and end boundaries swap
+    /// places. Shared subtrees stay shared, so this takes as many nodes as `self` does.
+    ///
+    /// ```
+    /// use fast_regex::regex::Regex;
+    ///
+    /// let r = Regex::from_pattern("ab*c").unwrap().reverse();
+    /// assert!(r.is_match("cbba"));
+    /// assert!(!r.is_match("abbc"));
+    /// ```
+    pub fn reverse(&self) -> Regex<'static> {
+        let mut alloc = VecAlloc::new(self.node_count());
+        let tree = loop {
+            // SAFETY: the tree is owned by this `Regex` or one of its parents, and `alloc` is new
+            let reversed = unsafe {
+                Self::copy_rec(&mut alloc, self.tree, false, |r| match r {
+                    Re::Seq(r1, r2) => Re::Seq(r2, r1),
+                    r => r,
+                })
+            };
+            match reversed {
+                Ok(tree) => break tree,
+                Err(_) => alloc.resize(),
+            }
+        };
+
+        Regex {
+            tree,
+            alloc,
+            normalizer: self.normalizer.clone(),
+            analyses: OnceLock::new(),
+            boundaries: Boundaries {
+                start: self.boundaries.end,
+                end: self.boundaries.start,
+            },
+            phantom: PhantomData,
+        }
+    }
+
+    /// The same as `matching_lines`.
+    pub fn lines_matching<'s, 't: 's>(
+        &'s self,
+        text: &'t str,
+    ) -> impl Iterator<Item = &'t str> + 's {
+        self.matching_lines(text)
+    }
+
+    /// Checks whether this `Regex` matches all of `line`, as if it was anchored with `^` and `$`.
+    /// One line ending (`\n` or `\r\n`) at the end of `line` is left out, so lines from `lines`
+    /// and from `BufRead::read_line` work the same.
+    pub fn matches_line(&self, line: &str) -> bool {
+        let line = match line.strip_suffix('\n') {
+            Some(line) => line.strip_suffix('\r').unwrap_or(line),
+            None => line,
+        };
+        self.is_match(line)
+    }
+}
+
+impl<A: Alphabet> Regex<'static, A> {
+    /// Like `clone`, but copies the internal buffer in one go and rebases the pointers in it,
+    /// instead of rebuilding the tree node by node. Nodes that point outside of the buffer (into a
+    /// `'static` parent) are left pointing there.
     pub fn clone_static(&self) -> Self {
-        unimplemented!("Use `clone` for now")
+        let mut alloc = self.alloc.duplicate();
+        // where each node of `self.alloc` lives in `alloc`
+        let nodes: Vec<_> = (0..alloc.len()).map(|i| alloc.get(i).unwrap()).collect();
+        let rebase = |r: Const<Re<A>>| match self.alloc.index_of(r.as_non_null()) {
+            Some(i) => Const::new(nodes[i]),
+            None => r,
+        };
+        let tree = rebase(self.tree);
+        for &node in &nodes {
+            // SAFETY: every node in `alloc` is initialised, and we don't hand out references to
+            // any of them until they have all been rebased
+            let rebased = match unsafe { node.as_ptr().read() } {
+                Re::Alt(r1, r2) => Re::Alt(rebase(r1), rebase(r2)),
+                Re::Seq(r1, r2) => Re::Seq(rebase(r1), rebase(r2)),
+                Re::And(r1, r2) => Re::And(rebase(r1), rebase(r2)),
+                Re::Star(r) => Re::Star(rebase(r)),
+                Re::Plus(r) => Re::Plus(rebase(r)),
+                Re::Opt(r) => Re::Opt(rebase(r)),
+                Re::Not(r) => Re::Not(rebase(r)),
+                Re::Mark(id, r) => Re::Mark(id, rebase(r)),
+                Re::AnyOf(set, len) => Re::AnyOf(rebase(set), len),
+                Re::NotAnyOf(set, len) => Re::NotAnyOf(rebase(set), len),
+                leaf => leaf,
+            };
+            // SAFETY: as above
+            unsafe { node.as_ptr().write(rebased) };
+        }
+
+        Regex {
+            tree,
+            alloc,
+            normalizer: self.normalizer.clone(),
+            analyses: self.analyses.clone(),
+            boundaries: self.boundaries,
+            phantom: PhantomData,
+        }
     }
 }
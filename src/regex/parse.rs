@@ -0,0 +1,239 @@
+use std::{error, fmt, str::FromStr};
+
+use super::build_plan::{self, ImplicitRe};
+use super::{Boundaries, Boundary, Regex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A `(` that is never closed.
+    UnclosedParen,
+    /// A `)` that was never opened.
+    UnopenedParen,
+    /// A repetition operator with nothing before it to repeat.
+    NothingToRepeat,
+    /// A `\` at the very end of the pattern.
+    TrailingEscape,
+    /// A `\b` or `\B` anywhere but the very start or end of the pattern, or in a pattern that
+    /// can't have boundaries at all.
+    MisplacedBoundary,
+}
+
+/// Where and why a pattern failed to parse. `offset` is a byte offset into the pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnclosedParen => write!(f, "unclosed `(`"),
+            Self::UnopenedParen => write!(f, "unopened `)`"),
+            Self::NothingToRepeat => write!(f, "repetition operator with nothing to repeat"),
+            Self::TrailingEscape => write!(f, "`\\` at the end of the pattern"),
+            Self::MisplacedBoundary => {
+                write!(
+                    f,
+                    "word boundary that isn't at the start or end of the pattern"
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.offset)
+    }
+}
+
+impl error::Error for ParseError {}
+
+struct Parser<'s> {
+    pattern: &'s str,
+    offset: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.pattern[self.offset..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            offset: self.offset,
+            kind,
+        }
+    }
+
+    /// alt := seq ('|' seq)*
+    ///
+    /// Branches that are written out more than once, like the second `cat` in `cat|dog|cat`, are
+    /// only kept once.
+    fn alt(&mut self) -> Result<build_plan::Re, ParseError> {
+        let mut branches = vec![self.seq()?];
+        while self.peek() == Some('|') {
+            self.bump();
+            let branch = self.seq()?;
+            if !branches.contains(&branch) {
+                branches.push(branch);
+            }
+        }
+        let mut branches = branches.into_iter();
+        let first = branches.next().unwrap();
+        Ok(branches.fold(first, |r, branch| r.alt(branch)))
+    }
+
+    /// seq := repeat*
+    fn seq(&mut self) -> Result<build_plan::Re, ParseError> {
+        let mut r: Option<build_plan::Re> = None;
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let rhs = self.repeat()?;
+            r = Some(match r {
+                Some(r) => r.seq(rhs),
+                None => rhs,
+            });
+        }
+        Ok(r.unwrap_or(build_plan::Re::One))
+    }
+
+    /// repeat := atom ('*' | '+' | '?')*
+    fn repeat(&mut self) -> Result<build_plan::Re, ParseError> {
+        let mut r = self.atom()?;
+        loop {
+            r = match self.peek() {
+                Some('*') => r.star(),
+                Some('+') => r.plus(),
+                Some('?') => r.opt(),
+                _ => return Ok(r),
+            };
+            self.bump();
+        }
+    }
+
+    /// atom := '(' alt ')' | '\' char | '.' | char
+    fn atom(&mut self) -> Result<build_plan::Re, ParseError> {
+        let start = self.offset;
+        match self.bump() {
+            Some('(') => {
+                let r = self.alt()?;
+                match self.bump() {
+                    Some(')') => Ok(r),
+                    _ => Err(ParseError {
+                        offset: start,
+                        kind: ParseErrorKind::UnclosedParen,
+                    }),
+                }
+            }
+            Some('\\') => match self.bump() {
+                Some('b' | 'B') => Err(ParseError {
+                    offset: start,
+                    kind: ParseErrorKind::MisplacedBoundary,
+                }),
+                Some(c) => Ok(c.re()),
+                None => Err(ParseError {
+                    offset: start,
+                    kind: ParseErrorKind::TrailingEscape,
+                }),
+            },
+            Some('.') => Ok(build_plan::Re::any()),
+            Some('*' | '+' | '?') => Err(ParseError {
+                offset: start,
+                kind: ParseErrorKind::NothingToRepeat,
+            }),
+            Some(c) => Ok(c.re()),
+            // `seq` only calls us when there is something left
+            None => unreachable!(),
+        }
+    }
+}
+
+fn boundary(escape: &str) -> Option<Boundary> {
+    match escape {
+        "\\b" => Some(Boundary::Word),
+        "\\B" => Some(Boundary::NotWord),
+        _ => None,
+    }
+}
+
+/// Parses `pattern[offset..]` to the end.
+fn parse_from(pattern: &str, offset: usize) -> Result<build_plan::Re, ParseError> {
+    let mut parser = Parser { pattern, offset };
+    let r = parser.alt()?;
+    match parser.peek() {
+        None => Ok(r),
+        Some(_) => Err(parser.error(ParseErrorKind::UnopenedParen)),
+    }
+}
+
+/// Parses `pattern` into a `build_plan::Re`.
+///
+/// Supports literals, alternation with `|`, concatenation, the repetitions `*`, `+` and `?`, `.`
+/// for any `char` and grouping with parentheses. Any of `\|*+?.()` can be escaped with a `\` to
+/// match it literally. Word boundaries are not supported,
+/// see `parse_with_boundaries`.
+pub fn parse(pattern: &str) -> Result<build_plan::Re, ParseError> {
+    parse_from(pattern, 0)
+}
+
+impl FromStr for build_plan::Re {
+    type Err = ParseError;
+
+    /// Same as `parse`.
+    fn from_str(pattern: &str) -> Result<Self, ParseError> {
+        parse(pattern)
+    }
+}
+
+/// Like `parse`, but also accepts a `\b` or `\B` word boundary at the very start and end of
+/// `pattern`, which are returned separately, for `Regex::with_boundaries`.
+pub fn parse_with_boundaries(pattern: &str) -> Result<(build_plan::Re, Boundaries), ParseError> {
+    let start = pattern.get(..2).and_then(boundary);
+    let offset = if start.is_some() { 2 } else { 0 };
+
+    let mut body = pattern;
+    let mut end = None;
+    let rest = &pattern[offset..];
+    if let Some(before) = rest
+        .strip_suffix("\\b")
+        .or_else(|| rest.strip_suffix("\\B"))
+    {
+        // a trailing `\b` only counts if its `\` isn't itself escaped
+        let escapes = before.bytes().rev().take_while(|&b| b == b'\\').count();
+        if escapes % 2 == 0 {
+            body = &pattern[..pattern.len() - 2];
+            end = boundary(&pattern[body.len()..]);
+        }
+    }
+
+    let r = parse_from(body, offset)?;
+    Ok((r, Boundaries { start, end }))
+}
+
+impl Regex<'static> {
+    /// Parses `pattern` with `parse_with_boundaries` and builds it, in one step. The `Regex` owns
+    /// all of its nodes, so it can be stored and moved around freely.
+    pub fn from_pattern(pattern: &str) -> Result<Self, ParseError> {
+        let (plan, boundaries) = parse_with_boundaries(pattern)?;
+        Ok(Regex::from(&plan).with_boundaries(boundaries))
+    }
+}
+
+impl TryFrom<&str> for Regex<'static> {
+    type Error = ParseError;
+
+    /// Same as `Regex::from_pattern`. The error has the byte offset the pattern went wrong at.
+    fn try_from(pattern: &str) -> Result<Self, ParseError> {
+        Regex::from_pattern(pattern)
+    }
+}
@@ -0,0 +1,60 @@
+use std::io::{self, Read};
+
+use super::*;
+
+/// How many bytes `is_match_reader` reads at a time.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+impl<'a> Regex<'a> {
+    /// Like `is_match`, but for the contents of `reader`, so that large files don't have to be
+    /// loaded all at once. The input is read in chunks and derived by as it comes in, stopping
+    /// early once no match is possible anymore.
+    ///
+    /// Errors from `reader` are returned as-is. Input that isn't valid UTF-8 is an
+    /// `InvalidData` error.
+    pub fn is_match_reader<R: Read>(&self, mut reader: R) -> io::Result<bool> {
+        let mut buf = vec![0; CHUNK_SIZE];
+        // the first `pending` bytes of `buf` are an incomplete char left over from the last read
+        let mut pending = 0;
+        let (mut first, mut last) = (None, None);
+        let mut r = self.clone();
+        loop {
+            let n = match reader.read(&mut buf[pending..]) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let len = pending + n;
+            let s = match std::str::from_utf8(&buf[..len]) {
+                Ok(s) => s,
+                // the chunk ends part way through a char, which the next read will finish
+                Err(e) if e.error_len().is_none() => {
+                    std::str::from_utf8(&buf[..e.valid_up_to()]).expect("valid up to here")
+                }
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            };
+            for c in s.chars() {
+                first.get_or_insert(c);
+                last = Some(c);
+                r = r.der(self.normalize(c)).simp().clone();
+                // SAFETY: dereferencing a reference to immutable buffers
+                if let Re::Zero = unsafe { r.tree.as_ref() } {
+                    return Ok(false);
+                }
+            }
+            let used = s.len();
+            buf.copy_within(used..len, 0);
+            pending = len - used;
+        }
+        if pending > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream ended part way through a char",
+            ));
+        }
+        Ok(r.nullable()
+            && self.boundaries.start.is_none_or(|b| b.holds(None, first))
+            && self.boundaries.end.is_none_or(|b| b.holds(last, None)))
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::BTreeSet;
+
+use super::*;
+
+/// Where and why an input was rejected, see `Regex::rejection_detail`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    /// The char position of the first `char` that can't be part of a match.
+    pub at_char: usize,
+    /// The offending `char`, or `None` if the input ended before the match could.
+    pub found: Option<char>,
+    /// Every `char` that would have been accepted instead, or `None` if that is too many to list,
+    /// see `Regex::first_set`.
+    pub expected: Option<BTreeSet<char>>,
+}
+
+impl<'a> Regex<'a> {
+    /// Explains why `is_match` rejects `s`, or returns `None` if it doesn't. Boundaries are not
+    /// checked, so this only explains rejections by the pattern itself.
+    pub fn rejection_detail(&self, s: &str) -> Option<Rejection> {
+        let mut r = self.clone();
+        let mut len = 0;
+        for (i, c) in s.chars().enumerate() {
+            let d = r.der(self.normalize(c)).simp().clone();
+            // SAFETY: dereferencing a reference to immutable buffers
+            if let Re::Zero = unsafe { d.tree.as_ref() } {
+                return Some(Rejection {
+                    at_char: i,
+                    found: Some(c),
+                    expected: r.first_set().cloned(),
+                });
+            }
+            r = d;
+            len = i + 1;
+        }
+
+        if r.nullable() {
+            None
+        } else {
+            Some(Rejection {
+                at_char: len,
+                found: None,
+                expected: r.first_set().cloned(),
+            })
+        }
+    }
+}
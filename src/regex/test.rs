@@ -1,46 +1,2407 @@
+use super::build_plan::ImplicitRe;
 use super::*;
 
+fn debug(r: &Regex) -> String {
+    format!("{:?}", r)
+}
+
 #[test]
 fn build_regex() {
-    todo!();
+    let r = Regex::from(&"ab".alt('c'));
+    assert_eq!(debug(&r), "Regex('a'.'b'|'c')");
+    assert!(!r.nullable());
+
+    let r = Regex::from(&'a'.star());
+    assert_eq!(debug(&r), "Regex('a'*)");
+    assert!(r.nullable());
+
+    assert_eq!(debug(&Regex::from(&"".re())), "Regex(1)");
 }
 
 #[test]
 fn der_star() {
-    todo!();
+    let r = Regex::from(&'a'.star());
+    assert_eq!(debug(&r.der('a')), "Regex(1.'a'*)");
+    assert_eq!(debug(&r.der('b')), "Regex(0.'a'*)");
 }
 
 #[test]
 fn der_one() {
-    todo!();
+    let r = Regex::from(&build_plan::Re::One);
+    assert_eq!(debug(&r.der('a')), "Regex(0)");
 }
 
 #[test]
 fn der_zero() {
-    todo!();
+    let r = Regex::from(&build_plan::Re::Zero);
+    assert_eq!(debug(&r.der('a')), "Regex(0)");
 }
 
 #[test]
 fn der_alt() {
-    todo!();
+    let r = Regex::from(&'a'.alt('b'));
+    assert_eq!(debug(&r.der('a')), "Regex(1|0)");
+    assert_eq!(debug(&r.der('b')), "Regex(0|1)");
 }
 
 #[test]
 fn der_seq() {
-    todo!();
+    let r = Regex::from(&'a'.seq('b'));
+    assert_eq!(debug(&r.der('a')), "Regex(1.'b')");
+
+    // a nullable head also derives the tail
+    let r = Regex::from(&'a'.star().seq('b'));
+    assert_eq!(debug(&r.der('b')), "Regex(0.'a'*.'b'|1)");
 }
 
 #[test]
 fn simp_seq() {
-    todo!()
+    let r = Regex::from(&"abc".re());
+    assert_eq!(debug(&r.der('a').simp()), "Regex('b'.'c')");
+    assert_eq!(debug(&r.der('b').simp()), "Regex(0)");
 }
 
 #[test]
 fn simp_alt() {
-    todo!()
+    let r = Regex::from(&'a'.alt('b'));
+    assert_eq!(debug(&r.der('a').simp()), "Regex(1)");
+    assert_eq!(debug(&r.der('c').simp()), "Regex(0)");
+    assert_eq!(debug(&Regex::from(&'a'.alt('a')).simp()), "Regex('a')");
 }
 
 #[test]
 fn example_strings() {
-    todo!()
-}
\ No newline at end of file
+    let r = Regex::from(&"ab".alt('c').star());
+    assert!(r.is_match(""));
+    assert!(r.is_match("ab"));
+    assert!(r.is_match("cabcc"));
+    assert!(!r.is_match("a"));
+    assert!(!r.is_match("abb"));
+
+    let r = Regex::from(&'a'.star().seq('b'));
+    assert!(r.is_match(&("a".repeat(10000) + "b")));
+    assert!(!r.is_match(&"a".repeat(10000)));
+}
+
+#[cfg(feature = "regex-syntax")]
+#[test]
+fn from_hir() {
+    let hir = regex_syntax::parse("[a-z]+").unwrap();
+    let r = Regex::from_hir(&hir).unwrap();
+    assert!(r.is_match("abc"));
+    assert!(r.is_match("z"));
+    assert!(!r.is_match(""));
+    assert!(!r.is_match("aBc"));
+
+    let r = Regex::from_hir(&regex_syntax::parse("(?:ab|c){2,3}").unwrap()).unwrap();
+    assert!(r.is_match("abc"));
+    assert!(r.is_match("cabc"));
+    assert!(!r.is_match("c"));
+    assert!(!r.is_match("cccc"));
+
+    let unsupported = |p| Regex::from_hir(&regex_syntax::parse(p).unwrap()).unwrap_err();
+    assert_eq!(unsupported("^a"), hir::UnsupportedFeature::Look);
+    assert_eq!(unsupported("(a)"), hir::UnsupportedFeature::Capture);
+}
+
+#[cfg(feature = "regex-syntax")]
+#[test]
+fn lift_to_hir() {
+    let plans = [
+        "a(b|c)*d".parse().unwrap(),
+        build_plan::Re::range('a', 'f')
+            .plus()
+            .seq(build_plan::Re::any().opt()),
+        build_plan::Re::one_of("xyz".chars()).seq(build_plan::Re::none_of("ab".chars())),
+        'a'.alt("bc").times(3).mark(1),
+        build_plan::Re::Zero.alt("").star(),
+    ];
+    let inputs = all_strings(&['a', 'b', 'c', 'd', 'x', 'é'], 4);
+    for plan in &plans {
+        let hir = hir::lift(plan).unwrap();
+        // printed as regex syntax, for the `regex` crate to compile too
+        let printed = hir.to_string();
+        let back = Regex::from_hir(&regex_syntax::parse(&printed).unwrap()).unwrap();
+        let r = Regex::from(plan);
+        for s in &inputs {
+            assert_eq!(back.is_match(s), r.is_match(s), "{plan} {printed} {s:?}");
+        }
+    }
+
+    assert_eq!(hir::lift(&'a'.and('b')), Err(hir::UnsupportedNode::And));
+    assert_eq!(
+        hir::lift(&'a'.seq('b'.complement())),
+        Err(hir::UnsupportedNode::Not)
+    );
+}
+
+#[test]
+fn longest_prefix() {
+    let r = Regex::from(&'a'.star());
+    assert_eq!(r.longest_prefix("aaab"), Some(3));
+    assert_eq!(r.longest_prefix("b"), Some(0));
+    assert_eq!(Regex::from(&'x'.re()).longest_prefix("yy"), None);
+    assert_eq!(
+        Regex::from(&"ab".alt("abcd")).longest_prefix("abcx"),
+        Some(2)
+    );
+    assert_eq!(Regex::from(&'é'.star()).longest_prefix("éé!"), Some(4));
+}
+
+#[test]
+fn simp_seq_star() {
+    let r = Regex::from(&'a'.star().seq('a'.star()));
+    assert_eq!(debug(&r.simp()), "Regex('a'*)");
+
+    let r = Regex::from(&'a'.star().seq('a'.star().seq('b')));
+    assert_eq!(debug(&r.simp()), "Regex('a'*.'b')");
+
+    let r = Regex::from(&'a'.star().seq('b'.star()));
+    assert_eq!(debug(&r.simp()), "Regex('a'*.'b'*)");
+}
+
+#[test]
+fn char_range_partition() {
+    assert_eq!(CharRange::partition([]), vec![CharRange::all()]);
+    assert_eq!(
+        CharRange::partition(['b', 'a', '\0']),
+        vec![
+            CharRange::single('\0'),
+            CharRange::new('\u{1}', '`'),
+            CharRange::single('a'),
+            CharRange::single('b'),
+            CharRange::new('c', char::MAX),
+        ]
+    );
+    assert_eq!(
+        CharRange::partition(['\u{E000}', char::MAX]),
+        vec![
+            CharRange::new('\0', '\u{D7FF}'),
+            CharRange::single('\u{E000}'),
+            CharRange::new('\u{E001}', '\u{10FFFE}'),
+            CharRange::single(char::MAX),
+        ]
+    );
+}
+
+#[test]
+fn alphabet() {
+    let digits = build_plan::Re::range('0', '9');
+    let r = Regex::from(
+        &"if"
+            .alt(digits.plus())
+            .alt(build_plan::Re::one_of(['x', 'y'])),
+    );
+    let alphabet = r.alphabet();
+    assert_eq!(
+        alphabet.chars.into_iter().collect::<Vec<_>>(),
+        ['0', '9', 'f', 'i', 'x', 'y']
+    );
+    assert!(!alphabet.has_other);
+    // the `char`s in between aren't listed, but every one of them has a class
+    let classes = r.derivative_classes();
+    assert!(classes.contains(&CharRange::new('1', '8')));
+    assert!(classes.contains(&CharRange::new('z', char::MAX)));
+    let alphabet = Regex::from(&build_plan::Re::Any).alphabet();
+    assert!(alphabet.chars.is_empty() && alphabet.has_other);
+
+    // `a` and `a|.` list the same `char`s, but only one of them matches anything else
+    let alphabet = Regex::from(&'a'.alt(build_plan::Re::Any)).alphabet();
+    assert!(alphabet.has_other);
+    assert_eq!(alphabet.chars, Regex::from(&'a'.re()).alphabet().chars);
+    assert!(Regex::from(&'a'.complement()).alphabet().has_other);
+    assert!(
+        Regex::from(&build_plan::Re::none_of(['a']))
+            .alphabet()
+            .has_other
+    );
+}
+
+#[test]
+fn walk_states() {
+    let r = Regex::from(&"ab".re());
+    let mut states = vec![];
+    r.walk_states(|state, accepting, transitions| {
+        assert_eq!(transitions.len(), 4);
+        states.push((format!("{:?}", state), accepting));
+    });
+    assert_eq!(states.len(), 4);
+    assert_eq!(
+        states
+            .iter()
+            .filter(|(_, accepting)| *accepting)
+            .collect::<Vec<_>>(),
+        vec![&("Regex(1)".to_string(), true)]
+    );
+}
+
+#[test]
+fn equivalent() {
+    let equivalent = |p1, p2| {
+        let (r1, r2) = (
+            Regex::from_pattern(p1).unwrap(),
+            Regex::from_pattern(p2).unwrap(),
+        );
+        assert_eq!(r1.equivalent(&r2), r2.equivalent(&r1), "{p1} and {p2}");
+        r1.equivalent(&r2)
+    };
+    assert!(equivalent("(a|b)*", "(b|a)*"));
+    assert!(equivalent("a*a*", "a*"));
+    assert!(equivalent("a|aa", "a(a|)"));
+    assert!(equivalent("(a*b*)*", "(a|b)*"));
+    assert!(equivalent("a+", "aa*"));
+    assert!(!equivalent("a*", "a+"));
+    assert!(!equivalent("(ab)*", "(a|b)*"));
+    // `x` is in neither pattern, but `.` matches it
+    assert!(!equivalent(".", "a|b"));
+
+    let (a, not_not_a) = (
+        Regex::from(&'a'.re()),
+        Regex::from(&'a'.complement().complement()),
+    );
+    assert!(a.equivalent(&not_not_a));
+    let nothing = Regex::from(&build_plan::Re::Zero);
+    assert!(Regex::from(&'a'.and('b')).equivalent(&nothing));
+
+    // the same answers as comparing all the short strings
+    let strings = all_strings(&['a', 'b', 'c'], 4);
+    let patterns = all_patterns(1);
+    for p1 in &patterns {
+        for p2 in &patterns {
+            let (r1, r2) = (Regex::from(p1), Regex::from(p2));
+            let same = strings.iter().all(|s| r1.is_match(s) == r2.is_match(s));
+            assert_eq!(r1.equivalent(&r2), same, "{p1} and {p2}");
+        }
+    }
+}
+
+/// Tracks the largest single allocation made on the current thread, so that tests can check that
+/// nothing proportional to the input is materialized.
+struct TrackingAlloc;
+
+thread_local! {
+    static LARGEST_ALLOC: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+fn track_alloc(size: usize) {
+    let _ = LARGEST_ALLOC.try_with(|largest| largest.set(largest.get().max(size)));
+}
+
+unsafe impl std::alloc::GlobalAlloc for TrackingAlloc {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        track_alloc(layout.size());
+        std::alloc::System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        std::alloc::System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: std::alloc::Layout, new_size: usize) -> *mut u8 {
+        track_alloc(new_size);
+        std::alloc::System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: TrackingAlloc = TrackingAlloc;
+
+#[test]
+fn is_match_large_input() {
+    let s = "a".repeat(1 << 18);
+    let r = Regex::from(&'a'.star());
+
+    LARGEST_ALLOC.with(|largest| largest.set(0));
+    assert!(r.is_match(&s));
+    // collecting into a `Vec<char>` would allocate 4 bytes per char
+    assert!(LARGEST_ALLOC.with(|largest| largest.get()) < s.len());
+}
+
+#[test]
+fn build_deep_plan() {
+    let mut plan = "".re();
+    for _ in 0..100_000 {
+        plan = 'a'.seq(plan);
+    }
+    let r = Regex::from(&plan);
+    assert_eq!(r.alloc().len(), 200_001);
+}
+
+#[test]
+fn analyses_deep_plan() {
+    let mut plan = "".re();
+    for _ in 0..100_000 {
+        plan = 'a'.seq(plan);
+    }
+    let r = Regex::from(&plan);
+    assert_eq!(r.length_bounds(), Some((100_000, Some(100_000))));
+    assert_eq!(
+        r.first_set(),
+        Some(&std::collections::BTreeSet::from(['a']))
+    );
+    assert_eq!(r.required_prefix(), ['a'; 100_000]);
+    // these are ruled out by the analyses, without deriving
+    assert!(!r.is_match(&"a".repeat(99_999)));
+    assert!(!r.is_match(&"a".repeat(100_001)));
+    assert!(!r.is_match(&format!("{}b", "a".repeat(99_999))));
+}
+
+#[test]
+fn derivative_by_class() {
+    let lower = ('b'..='z').fold(build_plan::Re::Char('a'), |r, c| r.alt(c));
+    let r = Regex::from(&lower.seq('x'));
+    assert_eq!(
+        debug(&r.derivative_by_class(CharRange::new('a', 'z'))),
+        "Regex('x')"
+    );
+    assert_eq!(
+        debug(&r.derivative_by_class(CharRange::new('{', char::MAX))),
+        "Regex(0)"
+    );
+}
+
+/// Pairs of equivalent patterns, one built with `build_plan` and one for the `regex` crate.
+fn battery() -> Vec<(build_plan::Re, &'static str)> {
+    vec![
+        ("".re(), ""),
+        (build_plan::Re::Zero, "[^\\s\\S]"),
+        ("abc".re(), "abc"),
+        ('a'.alt('b'), "a|b"),
+        ('a'.star(), "a*"),
+        ('a'.alt('b').star(), "(?:a|b)*"),
+        ("ab".star().seq('c'), "(?:ab)*c"),
+        ('a'.star().seq('a'.star()), "a*a*"),
+        ('a'.star().star(), "(?:a*)*"),
+        ('a'.star().seq('b').star(), "(?:a*b)*"),
+        ("ab".alt("abc").seq('c'.star()), "(?:ab|abc)c*"),
+        ('a'.alt("".re()).seq('b'), "(?:a|)b"),
+        ('a'.star().seq('b'.star()).seq('a'), "a*b*a"),
+        ('a'.plus(), "a+"),
+        ("ab".alt('c').plus().seq('c'), "(?:ab|c)+c"),
+        ('a'.star().plus(), "(?:a*)+"),
+        ('a'.seq('b'.opt()).seq('c'), "ab?c"),
+        ("ab".opt().plus(), "(?:(?:ab)?)+"),
+    ]
+}
+
+/// Every string over `alphabet` of length at most `len`.
+fn all_strings(alphabet: &[char], len: usize) -> Vec<String> {
+    let mut strings = vec![String::new()];
+    let mut last = vec![String::new()];
+    for _ in 0..len {
+        last = last
+            .iter()
+            .flat_map(|s| alphabet.iter().map(move |c| format!("{}{}", s, c)))
+            .collect();
+        strings.extend(last.iter().cloned());
+    }
+    strings
+}
+
+#[test]
+fn is_match_battery() {
+    let inputs = all_strings(&['a', 'b', 'c'], 5);
+    for (plan, pattern) in battery() {
+        let r = Regex::from(&plan);
+        let oracle = rust_regex::Regex::new(&format!("^(?:{})$", pattern)).unwrap();
+        for s in &inputs {
+            assert_eq!(
+                r.is_match(s),
+                oracle.is_match(s),
+                "{:?} over {:?}",
+                pattern,
+                s
+            );
+        }
+    }
+}
+
+#[test]
+fn matching_lines() {
+    let r = Regex::from(&"error:".re());
+    let text = "error: bad thing\nwarning: meh\nlinker error: also bad\n";
+    assert_eq!(
+        r.matching_lines(text).collect::<Vec<_>>(),
+        vec!["error: bad thing", "linker error: also bad"]
+    );
+    assert!(r.contains("error:"));
+    assert!(!r.contains("error"));
+    assert!(Regex::from(&'a'.star()).contains(""));
+
+    // the same with `\r\n` endings, which don't end up in the lines
+    let text = "error: bad thing\r\nwarning: meh\r\nlinker error: also bad";
+    assert_eq!(
+        r.lines_matching(text).collect::<Vec<_>>(),
+        vec!["error: bad thing", "linker error: also bad"]
+    );
+
+    let word = build_plan::Re::range('a', 'z').plus();
+    let r = Regex::from(&word.clone().seq(": ").seq(word));
+    assert!(r.matches_line("warning: meh"));
+    assert!(r.matches_line("warning: meh\n"));
+    assert!(r.matches_line("warning: meh\r\n"));
+    assert!(!r.matches_line("warning: meh\n\n"));
+    assert!(!r.matches_line("  warning: meh"));
+    // a `\r` on its own isn't a line ending
+    assert!(!r.matches_line("warning: meh\r"));
+    let lines: Vec<bool> = text.lines().map(|line| r.matches_line(line)).collect();
+    assert_eq!(lines, [false, true, false]);
+    let r = Regex::from(&"ab\r".re());
+    assert!(r.matches_line("ab\r"));
+    assert!(r.matches_line("ab\r\r\n"));
+}
+
+#[test]
+fn is_match_reader() {
+    use std::io::{Cursor, Read};
+
+    let r = Regex::from(&'a'.star().seq("é€b"));
+    let text = "aaaé€b";
+    // every way of splitting the input over two reads, including inside the multi-byte chars
+    for i in 0..=text.len() {
+        let (front, back) = text.as_bytes().split_at(i);
+        let reader = Cursor::new(front).chain(Cursor::new(back));
+        assert!(r.is_match_reader(reader).unwrap(), "split at {i}");
+    }
+    assert!(!r.is_match_reader(Cursor::new("aaaé€")).unwrap());
+
+    // longer than one chunk, with an `é` straddling the first two
+    let mut text = "a".repeat(8 * 1024 - 1);
+    text.push_str("é€b");
+    assert!(r.is_match_reader(Cursor::new(text.as_bytes())).unwrap());
+    text.push('c');
+    assert!(!r.is_match_reader(Cursor::new(text.as_bytes())).unwrap());
+
+    let error = r.is_match_reader(Cursor::new(b"a\xff")).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    let error = r
+        .is_match_reader(Cursor::new(&"é".as_bytes()[..1]))
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    struct Broken;
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("broken"))
+        }
+    }
+    let error = r
+        .is_match_reader(Cursor::new("aa").chain(Broken))
+        .unwrap_err();
+    assert_eq!(error.to_string(), "broken");
+    // a dead state stops reading before the error is reached
+    assert!(!r.is_match_reader(Cursor::new("b").chain(Broken)).unwrap());
+}
+
+#[test]
+fn parse() {
+    let r = Regex::from(&parse::parse("a(b|c)*d|\\*").unwrap());
+    assert!(r.is_match("ad"));
+    assert!(r.is_match("abcbd"));
+    assert!(r.is_match("*"));
+    assert!(!r.is_match("abc"));
+    assert!(Regex::from(&parse::parse("").unwrap()).is_match(""));
+
+    let plan = parse::parse("a+b?.\\.").unwrap();
+    assert_eq!(
+        plan,
+        'a'.plus()
+            .seq('b'.opt())
+            .seq(build_plan::Re::any())
+            .seq('.')
+    );
+    let r = Regex::from(&plan);
+    assert!(r.is_match("aabx."));
+    assert!(r.is_match("a.."));
+    assert!(r.is_match("ab!."));
+    assert!(!r.is_match("bx."));
+    assert!(!r.is_match("ax!"));
+    assert_eq!(parse::parse("a*?+").unwrap(), 'a'.star().opt().plus());
+
+    let error = |p| parse::parse(p).unwrap_err();
+    use parse::ParseErrorKind::*;
+    assert_eq!(error("a(b").kind, UnclosedParen);
+    assert_eq!(error("a(b").offset, 1);
+    assert_eq!(error("ab)").kind, UnopenedParen);
+    assert_eq!(error("ab)").offset, 2);
+    assert_eq!(error("a|*").kind, NothingToRepeat);
+    assert_eq!(error("(+a)").kind, NothingToRepeat);
+    assert_eq!(error("(+a)").offset, 1);
+    assert_eq!(error("?").kind, NothingToRepeat);
+    assert_eq!(error("a\\").kind, TrailingEscape);
+}
+
+#[test]
+fn from_str() -> Result<(), Box<dyn std::error::Error>> {
+    for (pattern, printed) in [
+        ("a(b|c)*", "Regex('a'.('b'|'c')*)"),
+        ("ab+|c?", "Regex('a'.'b'+|'c'?)"),
+        ("(.x)*", "Regex((..'x')*)"),
+    ] {
+        let plan: build_plan::Re = pattern.parse()?;
+        assert_eq!(debug(&Regex::from(&plan)), printed);
+    }
+
+    let error = "a(b".parse::<build_plan::Re>().unwrap_err();
+    assert_eq!(error.to_string(), "unclosed `(` at byte 1");
+    let error: Box<dyn std::error::Error> = Box::new(error);
+    assert!(error.to_string().contains("unclosed"));
+    Ok(())
+}
+
+#[test]
+fn display_plan() {
+    assert_eq!('a'.seq('b'.alt('c')).to_string(), "a(b|c)");
+    assert_eq!('a'.alt('b').star().to_string(), "(a|b)*");
+    assert_eq!("ab".plus().opt().to_string(), "(ab)+?");
+    assert_eq!("".re().star().to_string(), "()*");
+    assert_eq!('*'.seq(build_plan::Re::any()).to_string(), "\\*.");
+    assert_eq!(
+        'a'.and('b'.alt('c')).complement().seq('d').to_string(),
+        "!(a&(b|c))d"
+    );
+    assert_eq!('a'.star().complement().star().to_string(), "(!a*)*");
+    assert_eq!(
+        build_plan::Re::range('a', 'z')
+            .alt(build_plan::Re::Zero)
+            .to_string(),
+        "[a-z]|[]"
+    );
+
+    for pattern in ["a(b|c)*d", "(ab|c)+x?", "a|b|", ".\\(\\)", "((a|b)c)*"] {
+        let plan = parse::parse(pattern).unwrap();
+        assert_eq!(
+            parse::parse(&plan.to_string()).unwrap(),
+            plan,
+            "{}",
+            pattern
+        );
+    }
+
+    // deep plans don't overflow the stack
+    let deep = (0..100_000).fold(build_plan::Re::One, |r, _| r.star());
+    assert_eq!(deep.to_string().len(), 100_002);
+}
+
+#[test]
+fn get_or_compile() {
+    let r1 = Regex::get_or_compile("(ab)*").unwrap();
+    let r2 = Regex::get_or_compile("(ab)*").unwrap();
+    assert!(std::sync::Arc::ptr_eq(&r1, &r2));
+    assert!(r1.is_match("abab"));
+
+    Regex::clear_cache();
+    let r3 = Regex::get_or_compile("(ab)*").unwrap();
+    assert!(!std::sync::Arc::ptr_eq(&r1, &r3));
+    assert!(Regex::get_or_compile("(ab").is_err());
+}
+
+#[test]
+fn der_into() {
+    let mut arenas = [VecAlloc::new(4), VecAlloc::new(4)];
+    let mut r = Regex::from(&"ab".alt('c').star().seq('d'));
+    for (i, c) in "abccabd".chars().enumerate() {
+        let out = &mut arenas[i % 2];
+        out.clear();
+        let tree = r.der_into(c, out);
+        let d = r.der(c);
+        // SAFETY: `r` is alive and `out` is untouched since `der_into`
+        assert_eq!(format!("Regex({:?})", unsafe { tree.as_ref() }), debug(&d));
+        r = d.simp().clone();
+    }
+    assert!(r.nullable());
+
+    // an empty buffer grows once, and is then reused as it is
+    let mut out = VecAlloc::new(0);
+    let r = Regex::from(&"ab".alt('c').star().seq('d'));
+    r.der_into('a', &mut out);
+    let capacity = out.capacity();
+    assert!(capacity > 0);
+    for c in "abcd".chars() {
+        out.clear();
+        let tree = r.der_into(c, &mut out);
+        assert_eq!(out.capacity(), capacity);
+        // SAFETY: as above
+        assert_eq!(
+            format!("Regex({:?})", unsafe { tree.as_ref() }),
+            debug(&r.der(c))
+        );
+    }
+}
+
+#[test]
+fn char_normalizer() {
+    let r = Regex::from(&"a_b".re()).with_char_normalizer(|c| if c == '-' { '_' } else { c });
+    assert!(r.is_match("a-b"));
+    assert!(r.is_match("a_b"));
+    assert!(!r.is_match("a b"));
+    assert_eq!(r.longest_prefix("a-bc"), Some(3));
+    assert!(r.contains("xa-b"));
+    assert!(r.der('a').simp().clone().is_match("-b"));
+}
+
+#[test]
+fn analyze_complexity() {
+    use analysis::{Issue, Severity};
+
+    let report = Regex::from(&'a'.star().star()).analyze_complexity();
+    assert_eq!(report.severity, Severity::High);
+    assert_eq!(
+        report.worst,
+        Some((Issue::NestedStar, "('a'*)*".to_string()))
+    );
+
+    let report = Regex::from(&"ab".alt("ac")).analyze_complexity();
+    assert_eq!(report.severity, Severity::Moderate);
+    assert_eq!(report.worst.unwrap().0, Issue::AmbiguousAlt);
+
+    let report = Regex::from(&"abc".re()).analyze_complexity();
+    assert_eq!(report.severity, Severity::Safe);
+    assert_eq!(report.worst, None);
+
+    // too deep to print, so a large pattern is only summed up
+    let mut plan = "".re();
+    for _ in 0..100_000 {
+        plan = 'a'.seq(plan);
+    }
+    let report = Regex::from(&plan).analyze_complexity();
+    assert_eq!(report.severity, Severity::Moderate);
+    assert_eq!(
+        report.worst,
+        Some((
+            Issue::Large(200_001),
+            "200001 nodes, with a Seq at the root".to_string()
+        ))
+    );
+}
+
+#[test]
+fn first_set() {
+    let r = Regex::from(&'a'.star().seq('b').alt('c'.seq('d')));
+    assert_eq!(r.first_set().unwrap().iter().collect::<String>(), "abc");
+    assert_eq!(Regex::from(&'a'.complement()).first_set(), None);
+}
+
+#[test]
+fn regex_builder() {
+    let words: Vec<Regex> = (0..100)
+        .map(|i| Regex::from(&format!("w{}", i).as_str().re()))
+        .collect();
+    let r = words[1..]
+        .iter()
+        .fold(RegexBuilder::new(&words[0]), |b, r| b.alt(r))
+        .build();
+    assert!(r.is_match("w0"));
+    assert!(r.is_match("w42"));
+    assert!(r.is_match("w99"));
+    assert!(!r.is_match("w100"));
+
+    let separate: usize = words.iter().map(|r| r.alloc().capacity()).sum();
+    assert_eq!(r.alloc().len(), r.alloc().capacity());
+    assert!(r.alloc().capacity() < separate);
+
+    let r = RegexBuilder::new(&words[1])
+        .seq(&words[2])
+        .alt(&words[3])
+        .build();
+    assert_eq!(debug(&r), "Regex('w'.'1'.'w'.'2'|'w'.'3')");
+}
+
+#[test]
+fn reverse() {
+    let r = Regex::from(&"ab".re());
+    assert!(r.reverse().is_match("ba"));
+    assert!(!r.reverse().is_match("ab"));
+
+    let strings = all_strings(&['a', 'b'], 4);
+    for plan in all_patterns(1) {
+        let r = Regex::from(&plan);
+        let reversed = r.reverse();
+        assert!(reversed.reverse().equivalent(&r), "{plan}");
+        // the same as reversing the plan
+        let plan_reversed = Regex::from(&plan.clone().transform().reverse().into_plan());
+        assert!(reversed.equivalent(&plan_reversed), "{plan}");
+        for s in &strings {
+            let backwards: String = s.chars().rev().collect();
+            assert_eq!(
+                reversed.is_match(&backwards),
+                r.is_match(s),
+                "{plan} on {s:?}"
+            );
+        }
+    }
+
+    // `times` shares one copy of the pattern, and so does its reverse
+    let r = Regex::from(&"ab".times(1000));
+    assert_eq!(r.reverse().node_count(), r.node_count());
+    assert!(r.reverse().is_match(&"ba".repeat(1000)));
+
+    let r = Regex::from_pattern("\\bab").unwrap().reverse();
+    assert!(r.contains("xx ba"));
+    assert!(!r.contains("xx bax"));
+}
+
+#[test]
+fn child_resize() {
+    let r = Regex::from(&"ab".re());
+    let mut child = r.child();
+    // SAFETY: nothing has been allocated on the child's allocator, so nothing can dangle
+    let alloc = unsafe { child.alloc_mut() };
+    assert_eq!(alloc.capacity(), 0);
+    assert!(alloc.alloc(Re::One).is_err());
+    // one resize is enough, instead of doubling zero forever
+    alloc.resize();
+    assert_eq!(alloc.capacity(), VecAlloc::<Re>::MIN_CAPACITY);
+    assert!(alloc.alloc(Re::One).is_ok());
+    assert!(child.der('a').der('b').nullable());
+}
+
+#[test]
+fn compact() {
+    // the pathological case from the benchmark: unsimplified, its derivatives repeat the same
+    // branches over and over
+    let r = Regex::from_pattern("(a*)*b").unwrap();
+    let d1 = r.der('a');
+    let d2 = d1.der('a');
+    let d3 = d2.der('a');
+    let d = d3.der('a');
+    let compacted = d.compact();
+    assert_eq!(d.node_count(), 65);
+    assert_eq!(compacted.alloc().len(), 26);
+    assert_eq!(compacted, d);
+    for s in all_strings(&['a', 'b'], 4) {
+        assert_eq!(compacted.is_match(&s), d.is_match(&s), "{s:?}");
+    }
+    // nothing left to share
+    assert_eq!(compacted.compact().alloc().len(), 26);
+
+    // every word ends in a `0`, so those are shared
+    let words: Vec<Regex> = (1..10)
+        .map(|i| Regex::from(&format!("w{i}0").as_str().re()))
+        .collect();
+    let builder = || {
+        words[1..]
+            .iter()
+            .fold(RegexBuilder::new(&words[0]), |b, r| b.alt(r))
+    };
+    let (r, shared) = (builder().build(), builder().hash_consing(true).build());
+    assert_eq!(r, shared);
+    assert!(shared.alloc().len() < r.alloc().len());
+    assert!(shared.is_match("w50"));
+}
+
+/// Every end position of a match of `r` in `s` starting from `i`, by brute force.
+fn naive_ends(r: &build_plan::Re, s: &[char], i: usize) -> std::collections::BTreeSet<usize> {
+    use build_plan::Re::*;
+    match r {
+        Zero => [].into(),
+        One => [i].into(),
+        Char(c) => match s.get(i) {
+            Some(d) if c == d => [i + 1].into(),
+            _ => [].into(),
+        },
+        Any if i < s.len() => [i + 1].into(),
+        Any => [].into(),
+        Range(lo, hi) => match s.get(i) {
+            Some(d) if (lo..=hi).contains(&d) => [i + 1].into(),
+            _ => [].into(),
+        },
+        AnyOf(chars) => match s.get(i) {
+            Some(d) if chars.contains(d) => [i + 1].into(),
+            _ => [].into(),
+        },
+        NotAnyOf(chars) => match s.get(i) {
+            Some(d) if !chars.contains(d) => [i + 1].into(),
+            _ => [].into(),
+        },
+        Alt(r1, r2) => &naive_ends(r1, s, i) | &naive_ends(r2, s, i),
+        Seq(r1, r2) => naive_ends(r1, s, i)
+            .into_iter()
+            .flat_map(|j| naive_ends(r2, s, j))
+            .collect(),
+        And(r1, r2) => &naive_ends(r1, s, i) & &naive_ends(r2, s, i),
+        Star(r) => repeat_ends(r, s, [i].into()),
+        Plus(r) => repeat_ends(r, s, naive_ends(r, s, i)),
+        Opt(r) => &naive_ends(r, s, i) | &[i].into(),
+        Mark(_, r) => naive_ends(r, s, i),
+        Repeat(r, n) => (0..*n).fold([i].into(), |ends, _| {
+            ends.into_iter().flat_map(|j| naive_ends(r, s, j)).collect()
+        }),
+        Not(r) => {
+            let ends = naive_ends(r, s, i);
+            (i..=s.len()).filter(|j| !ends.contains(j)).collect()
+        }
+    }
+}
+
+/// Every end position reachable from one of `ends` by matching `r` any number of times.
+fn repeat_ends(
+    r: &build_plan::Re,
+    s: &[char],
+    mut ends: std::collections::BTreeSet<usize>,
+) -> std::collections::BTreeSet<usize> {
+    let mut frontier: Vec<usize> = ends.iter().copied().collect();
+    while let Some(j) = frontier.pop() {
+        for k in naive_ends(r, s, j) {
+            if ends.insert(k) {
+                frontier.push(k);
+            }
+        }
+    }
+    ends
+}
+
+/// Every pattern over `a` and `b` with at most `depth` levels of operators.
+fn all_patterns(depth: usize) -> Vec<build_plan::Re> {
+    let mut patterns = vec![build_plan::Re::Zero, "".re(), 'a'.re(), 'b'.re()];
+    for _ in 0..depth {
+        let smaller = patterns.clone();
+        for r1 in &smaller {
+            patterns.push(r1.clone().star());
+            patterns.push(r1.clone().plus());
+            patterns.push(r1.clone().opt());
+            patterns.push(r1.clone().complement());
+            for r2 in &smaller {
+                patterns.push(r1.clone().alt(r2.clone()));
+                patterns.push(r1.clone().seq(r2.clone()));
+                patterns.push(r1.clone().and(r2.clone()));
+            }
+        }
+    }
+    patterns
+}
+
+#[test]
+fn is_match_exhaustive() {
+    let inputs: Vec<Vec<char>> = all_strings(&['a', 'b'], 4)
+        .iter()
+        .map(|s| s.chars().collect())
+        .collect();
+    // Two levels of operators over the leaves 0, 1, a and b is every pattern up to depth 3,
+    // counting the leaves. That's about 14k patterns, and one more level would be about 600M.
+    let patterns = all_patterns(2);
+    assert_eq!(patterns.iter().map(|p| p.depth()).max(), Some(3));
+    for plan in patterns {
+        let r = Regex::from(&plan);
+        for s in &inputs {
+            assert_eq!(
+                r.is_match(&s.iter().collect::<String>()),
+                naive_ends(&plan, s, 0).contains(&s.len()),
+                "{:?} over {:?}",
+                r,
+                s
+            );
+        }
+    }
+}
+
+#[test]
+fn enumerate() {
+    let r = Regex::from(&'a'.seq('b').alt('c'.star()));
+    assert_eq!(r.enumerate(2), vec!["", "c", "ab", "cc"]);
+
+    // `a` and `b` lead to the same state, so only `a` is listed
+    let r = Regex::from(&'a'.alt('b').seq('c'.star()));
+    assert_eq!(r.enumerate(2), vec!["a", "ac"]);
+
+    let lower = ('b'..='z').fold(build_plan::Re::Char('a'), |r, c| r.alt(c));
+    let r = Regex::from(&lower.seq('a'));
+    assert_eq!(r.enumerate(5), vec!["aa"]);
+
+    assert!(Regex::from(&build_plan::Re::Zero).enumerate(3).is_empty());
+}
+
+#[test]
+fn alphabet_parity() {
+    let inputs = all_strings(&['a', 'b', 'é'], 4);
+    let mut plans: Vec<_> = battery().into_iter().map(|(plan, _)| plan).collect();
+    plans.push("é".re().alt("éa".star()));
+    for plan in &plans {
+        let chars = Regex::from(plan);
+        let bytes = Regex::<Bytes>::from_plan(plan);
+        for s in &inputs {
+            assert_eq!(
+                chars.is_match(s),
+                bytes.is_match_symbols(s.bytes()),
+                "{:?}",
+                s
+            );
+        }
+    }
+
+    let r = Regex::<Bytes>::from_plan(&'é'.re());
+    assert_eq!(format!("{:?}", r), "Regex(195.169)");
+    assert!(!r.is_match_symbols([195]));
+}
+
+#[test]
+fn cached_analyses() {
+    let r = Regex::from(&"ab".seq('c'.star()).alt("abd".re()));
+    assert_eq!(r.length_bounds(), Some((2, None)));
+    assert_eq!(r.required_prefix(), ['a', 'b']);
+    assert_eq!(
+        Regex::from(&'a'.seq(build_plan::Re::Zero)).length_bounds(),
+        None
+    );
+    assert_eq!(
+        Regex::from(&'a'.alt("bc")).length_bounds(),
+        Some((1, Some(2)))
+    );
+
+    let computed = || analysis::ANALYSES_COMPUTED.with(|n| n.get());
+    let r = Regex::from(&"ab".seq('c'.star()));
+    let before = computed();
+    assert!(r.is_match("abcc"));
+    assert!(!r.is_match("ac"));
+    assert_eq!(computed(), before + 1);
+    // derivatives are different `Regex`es, with their own analyses
+    assert_eq!(r.der('a').required_prefix(), ['b']);
+    assert_eq!(computed(), before + 2);
+}
+
+#[test]
+fn word_boundaries() {
+    let r = Regex::get_or_compile("\\bcat\\b").unwrap();
+    assert!(r.contains("a cat!"));
+    assert!(r.contains("cat"));
+    assert!(!r.contains("category"));
+    assert!(!r.contains("bobcat"));
+    assert!(r.is_match("cat"));
+
+    let r = Regex::get_or_compile("\\Bcat").unwrap();
+    assert!(r.contains("bobcat"));
+    assert!(!r.contains("a cat"));
+    assert_eq!(r.longest_prefix("cat"), None);
+
+    // an escaped `\` before the `b` is just a literal `\` then `b`
+    let (_, boundaries) = parse::parse_with_boundaries("a\\\\b").unwrap();
+    assert_eq!(boundaries, Boundaries::default());
+
+    use parse::ParseErrorKind::MisplacedBoundary;
+    assert_eq!(parse::parse("\\bcat").unwrap_err().kind, MisplacedBoundary);
+    let error = parse::parse_with_boundaries("a\\bb").unwrap_err();
+    assert_eq!((error.offset, error.kind), (1, MisplacedBoundary));
+
+    // patterns that end in a multi-byte char, with and without boundaries
+    assert!(Regex::from_pattern("aé").unwrap().is_match("aé"));
+    let r = Regex::from_pattern("\\bé").unwrap();
+    assert!(r.contains("x é"));
+    assert!(!r.contains("xé"));
+    let r = Regex::from_pattern("é\\b").unwrap();
+    assert!(r.contains("é x"));
+    assert!(!r.contains("éx"));
+    let (_, boundaries) = parse::parse_with_boundaries("\\Bcafé\\b").unwrap();
+    assert_eq!(boundaries.start, Some(Boundary::NotWord));
+    assert_eq!(boundaries.end, Some(Boundary::Word));
+}
+
+#[test]
+fn canonicalize() {
+    let canonical = |p| Regex::from(&parse::parse(p).unwrap()).canonicalize();
+    let mut patterns = std::collections::HashMap::new();
+    for p in ["a|aa", "aa|a", "a(a|)", "(|a)a", "a|aa|a"] {
+        patterns.entry(canonical(p)).or_insert(p);
+    }
+    assert_eq!(patterns.len(), 1);
+
+    assert_eq!(canonical("a*"), canonical("a*a*"));
+    assert_ne!(canonical("a|aa"), canonical("a"));
+    assert_ne!(canonical("a*"), canonical("b*"));
+}
+
+#[test]
+fn plan_fold() {
+    let cd = build_plan::Re::one_of(['c', 'd']);
+    let plan = "ab".alt(cd).star().seq('e'.opt());
+    let upper = plan.clone().map_chars(|c| c.to_ascii_uppercase());
+    let cd = build_plan::Re::one_of(['C', 'D']);
+    assert_eq!(upper, "AB".alt(cd).star().seq('E'.opt()));
+    let r = Regex::from(&upper);
+    assert!(r.is_match("ABDE"));
+    assert!(!r.is_match("abde"));
+
+    // the same counts as the built-in walks
+    assert_eq!(
+        plan.fold(|_, n: Vec<usize>| 1 + n.iter().sum::<usize>()),
+        plan.node_count()
+    );
+    let depth = |_: &build_plan::Re, d: Vec<usize>| 1 + d.into_iter().max().unwrap_or(0);
+    assert_eq!(plan.fold(depth), plan.depth());
+    // the children come in order
+    let spelled = plan.fold(|r, parts: Vec<String>| match r {
+        build_plan::Re::Char(c) => c.to_string(),
+        _ => parts.concat(),
+    });
+    assert_eq!(spelled, "abe");
+}
+
+#[test]
+fn seq_nests_to_the_right() {
+    // ((a.b).c) and (a.(b.c)) build to the same tree
+    let left = Regex::from(&'a'.seq('b').seq('c'));
+    let right = Regex::from(&'a'.seq('b'.seq('c')));
+    assert_eq!(left, right);
+
+    // so do the concatenations derivatives make, once simplified: this is (y.z).(x.y.z)*
+    let r = Regex::from(&"xyz".re().star());
+    assert_eq!(
+        r.der('x').simp(),
+        Regex::from(&"yz".re().seq("xyz".re().star()))
+    );
+    assert_eq!(r.der_str("xyzx"), r.der('x').simp());
+
+    // however long the chain is
+    let r = Regex::from(&"wxyz".re().star());
+    assert_eq!(
+        r.der('w').simp(),
+        Regex::from(&"xyz".re().seq("wxyz".re().star()))
+    );
+    assert_eq!(r.der_str("wxyzw"), r.der('w').simp());
+
+    // `times` shares the halves of a repetition, which has to survive simplifying
+    let r = Regex::from(&"ab".times(1000));
+    assert!(r.der('a').simp().depth() < 40);
+    assert!(r.is_match(&"ab".repeat(1000)));
+}
+
+#[test]
+fn der_star_outlives_parent_via_clone() {
+    // `der` of a `Star` points into the parent's arena, so only the clone may outlive it. Run
+    // under Miri to check that nothing in the clone still points at the dropped parent.
+    let d = {
+        let r = Regex::from(&'a'.seq('b').star());
+        let d = r.der('a');
+        d.clone()
+    };
+    assert_eq!(debug(&d), "Regex(1.'b'.('a'.'b')*)");
+    assert!(d.der('b').nullable());
+}
+
+#[test]
+fn match_start_bitset() {
+    let r = Regex::from(&"ab".re());
+    assert_eq!(
+        r.match_start_bitset("abab"),
+        [true, false, true, false, false]
+    );
+
+    let r = Regex::from(&'é'.star());
+    assert_eq!(r.match_start_bitset("aé"), [true; 3]);
+}
+
+#[test]
+fn plan_transform() {
+    let r = "Hi"
+        .re()
+        .alt('x'.seq(build_plan::Re::Zero))
+        .transform()
+        .case_fold()
+        .simplify()
+        .compile();
+    for s in ["hi", "Hi", "hI", "HI"] {
+        assert!(r.is_match(s), "{:?}", s);
+    }
+    assert!(!r.is_match("x"));
+    assert_eq!(debug(&r), "Regex(('H'|'h').('i'|'I'))");
+
+    let plan = "ab"
+        .alt("ac")
+        .alt("db".re())
+        .transform()
+        .factor_common_affixes()
+        .into_plan();
+    assert_eq!(plan, 'a'.seq('b'.alt('c')).alt("db"));
+    let plan = "ab"
+        .alt("cb")
+        .transform()
+        .factor_common_affixes()
+        .into_plan();
+    assert_eq!(plan, 'a'.alt('c').seq('b'));
+
+    let r = "abc".star().seq('d').transform().reverse().compile();
+    assert!(r.is_match("dcbacba"));
+    assert!(!r.is_match("abcd"));
+
+    let plan = 'a'
+        .star()
+        .star()
+        .seq("".re())
+        .transform()
+        .simplify()
+        .into_plan();
+    assert_eq!(plan, 'a'.star());
+}
+
+#[test]
+fn rejection_detail() {
+    let r = Regex::from(&'a'.seq('b'.alt('c')).seq('d'));
+    assert_eq!(r.rejection_detail("abd"), None);
+    assert_eq!(
+        r.rejection_detail("axd"),
+        Some(Rejection {
+            at_char: 1,
+            found: Some('x'),
+            expected: Some(['b', 'c'].into()),
+        })
+    );
+    assert_eq!(
+        r.rejection_detail("ac"),
+        Some(Rejection {
+            at_char: 2,
+            found: None,
+            expected: Some(['d'].into()),
+        })
+    );
+    // too long
+    assert_eq!(
+        r.rejection_detail("acdd").unwrap().expected,
+        Some([].into())
+    );
+}
+
+#[test]
+fn structural_eq() {
+    let plan = 'a'.alt('b').star().seq('c');
+    assert_eq!(Regex::from(&plan), Regex::from(&plan));
+    assert_eq!(Regex::from(&plan), Regex::from(&plan).clone());
+    assert_eq!(
+        Regex::from(&"abc".re()).der('a').simp(),
+        Regex::from(&"bc".re())
+    );
+
+    // same language, different shape
+    assert_ne!(Regex::from(&'a'.alt('b')), Regex::from(&'b'.alt('a')));
+    assert_ne!(Regex::from(&'a'.star()), Regex::from(&'a'.star().star()));
+
+    // consistent with `Ord`
+    let (r1, r2) = (
+        Regex::from(&plan),
+        Regex::from(&'a'.alt('c').star().seq('c')),
+    );
+    assert_eq!(r1.cmp(&Regex::from(&plan)), std::cmp::Ordering::Equal);
+    assert_ne!(r1.cmp(&r2), std::cmp::Ordering::Equal);
+    assert_ne!(r1, r2);
+
+    // shared subtrees, and trees too deep to compare recursively
+    assert_eq!(Regex::from(&'a'.times(1000)), Regex::from(&'a'.times(1000)));
+    assert_ne!(Regex::from(&'a'.times(1000)), Regex::from(&'a'.times(1001)));
+    let long = "ab".repeat(50_000);
+    assert_eq!(Regex::from(&long.re()), Regex::from(&long.re()));
+    assert_ne!(
+        Regex::from(&long.re()),
+        Regex::from(&(long.clone() + "a").re())
+    );
+}
+
+#[test]
+fn der_str() {
+    let r = Regex::from(&"abc".re());
+    let d = r.der_str("ab");
+    assert!(d.is_match("c"));
+    assert!(!d.is_match("bc"));
+    assert_eq!(d, Regex::from(&'c'.re()));
+    assert_eq!(r.der_str(""), r);
+    assert_eq!(debug(&r.der_str("abcd")), "Regex(0)");
+}
+
+#[test]
+fn matches_nothing_or_everything() {
+    assert!(Regex::from(&build_plan::Re::Zero).matches_nothing());
+    assert!(Regex::from(
+        &'a'.seq(build_plan::Re::Zero)
+            .star()
+            .seq('b'.alt(build_plan::Re::Zero))
+            .seq(build_plan::Re::Zero)
+    )
+    .matches_nothing());
+    assert!(!Regex::from(&"".re()).matches_nothing());
+    assert!(!Regex::from(&build_plan::Re::Zero.star()).matches_nothing());
+
+    assert!(!Regex::from(&"".re()).matches_everything());
+    assert!(!Regex::from(&'a'.alt('b').star()).matches_everything());
+    assert!(!Regex::from(&build_plan::Re::Zero).matches_everything());
+}
+
+#[test]
+fn class_across_surrogate_gap() {
+    // `[\u{D7F0}-\u{E00F}]`, which has no `char`s in the middle
+    let class = ('\u{D7F1}'..='\u{E00F}').fold(build_plan::Re::Char('\u{D7F0}'), |r, c| r.alt(c));
+    let r = Regex::from(&class);
+
+    let classes = r.derivative_classes();
+    assert_eq!(classes.len(), 34);
+    assert_eq!(classes[0], CharRange::new('\0', '\u{D7EF}'));
+    assert_eq!(classes[16], CharRange::single('\u{D7FF}'));
+    assert_eq!(classes[17], CharRange::single('\u{E000}'));
+    for class in &classes {
+        let c = class.representative();
+        assert!(class.contains(c));
+        assert!(!(0xD800..=0xDFFF).contains(&(c as u32)));
+    }
+
+    let straddling = CharRange::new('\u{D7F0}', '\u{E00F}');
+    assert_eq!(straddling.representative(), '\u{D7F0}');
+    assert_eq!(debug(&r.derivative_by_class(straddling)), "Regex(1)");
+    assert_eq!(r.enumerate(1), vec!["\u{D7F0}"]);
+}
+
+#[test]
+fn duplicate_alternations() {
+    let plan = parse::parse("cat|dog|cat").unwrap();
+    assert_eq!(plan, "cat".alt("dog"));
+    let r = Regex::from(&plan);
+    assert!(r.is_match("cat"));
+    assert!(r.is_match("dog"));
+
+    let simplify = |plan: build_plan::Re| plan.transform().simplify().into_plan();
+    assert_eq!(simplify("cat".alt("dog").alt("cat")), "cat".alt("dog"));
+    assert_eq!(simplify("cat".re().alt("dog".alt("cat"))), "dog".alt("cat"));
+    assert_eq!(simplify('a'.alt('b').alt('c')), 'a'.alt('b').alt('c'));
+}
+
+#[test]
+fn match_with_budget() {
+    let r = Regex::from(&"ab".star().seq('c'));
+    assert_eq!(r.match_with_budget("ababc", 5), BudgetResult::Matched);
+    assert_eq!(r.match_with_budget("abx", 5), BudgetResult::NotMatched);
+    // runs out of input before the budget, but the result is still known
+    assert_eq!(r.match_with_budget("abab", 5), BudgetResult::NotMatched);
+
+    let resume = |s: &str, budget| {
+        let mut state = r.clone();
+        let mut s = s;
+        let mut rounds = 0;
+        loop {
+            rounds += 1;
+            match state.match_with_budget(s, budget) {
+                BudgetResult::Exhausted {
+                    consumed,
+                    resumable_state,
+                } => {
+                    s = &s[consumed..];
+                    state = resumable_state;
+                }
+                result => return (result, rounds),
+            }
+        }
+    };
+    assert_eq!(resume("ababababc", 2), (BudgetResult::Matched, 5));
+    assert_eq!(resume("abababab", 3), (BudgetResult::NotMatched, 3));
+
+    let r = Regex::get_or_compile("ab\\b").unwrap();
+    let BudgetResult::Exhausted {
+        consumed,
+        resumable_state,
+    } = r.match_with_budget("ab", 1)
+    else {
+        panic!("expected to run out of budget");
+    };
+    assert_eq!(consumed, 1);
+    assert_eq!(
+        resumable_state.match_with_budget("b", 1),
+        BudgetResult::Matched
+    );
+}
+
+#[test]
+fn plus() {
+    let r = Regex::from(&'a'.plus());
+    assert!(!r.nullable());
+    assert!(Regex::from(&'a'.star().plus()).nullable());
+    assert_eq!(debug(&r.der('a')), "Regex(1.'a'*)");
+    assert_eq!(debug(&r.der('a').simp()), "Regex('a'*)");
+    assert!(r.is_match("aaa"));
+    assert!(!r.is_match(""));
+
+    assert_eq!(debug(&Regex::from(&"ab".plus())), "Regex(('a'.'b')+)");
+    assert_eq!(
+        debug(&Regex::from(&'a'.alt('b').star())),
+        "Regex(('a'|'b')*)"
+    );
+    assert_eq!(debug(&Regex::from(&'a'.plus().star())), "Regex(('a'+)*)");
+
+    let simplify = |plan: build_plan::Re| plan.transform().simplify().into_plan();
+    assert_eq!(simplify('a'.plus().plus()), 'a'.plus());
+    assert_eq!(simplify('a'.plus().star()), 'a'.star());
+    assert_eq!(simplify('a'.star().plus()), 'a'.star());
+}
+
+#[test]
+fn opt() {
+    let r = Regex::from(&'a'.seq('b'.opt()).seq('c'));
+    assert_eq!(debug(&r), "Regex('a'.'b'?.'c')");
+    assert!(r.is_match("ac"));
+    assert!(r.is_match("abc"));
+    assert!(!r.is_match("abbc"));
+
+    let r = Regex::from(&"ab".opt());
+    assert!(r.nullable());
+    assert_eq!(debug(&r.der('a').simp()), "Regex('b')");
+    assert_eq!(debug(&Regex::from(&'a'.star().opt()).simp()), "Regex('a'*)");
+    assert_eq!(
+        debug(&Regex::from(&build_plan::Re::Zero.opt()).simp()),
+        "Regex(1)"
+    );
+
+    let simplify = |plan: build_plan::Re| plan.transform().simplify().into_plan();
+    assert_eq!(simplify('a'.opt().opt()), 'a'.opt());
+    assert_eq!(simplify('a'.plus().opt()), 'a'.star());
+}
+
+#[test]
+fn complement() {
+    let r = Regex::from(&"ab".complement());
+    assert_eq!(debug(&r), "Regex(!('a'.'b'))");
+    assert!(r.nullable());
+    assert!(r.is_match("a"));
+    assert!(r.is_match("abb"));
+    assert!(!r.is_match("ab"));
+    assert_eq!(debug(&r.der('a').simp()), "Regex(!'b')");
+
+    // anything without an `a` in it
+    let r = Regex::from(
+        &build_plan::Re::Zero
+            .complement()
+            .seq('a')
+            .seq(build_plan::Re::Zero.complement())
+            .complement(),
+    );
+    assert!(r.is_match("bcd"));
+    assert!(!r.is_match("bad"));
+
+    assert_eq!(
+        debug(&Regex::from(&'a'.complement().complement()).simp()),
+        "Regex('a')"
+    );
+    assert!(Regex::from(&build_plan::Re::Zero.complement()).matches_everything());
+    assert!(Regex::from(&'a'.star().complement().seq('b')).contains("xb"));
+    // `length_bounds` can't tell that this is empty on its own
+    assert!(Regex::from(&'a'.complement().alt('a').complement()).matches_nothing());
+    assert!(!Regex::from(&'a'.star().complement()).matches_nothing());
+
+    let simplify = |plan: build_plan::Re| plan.transform().simplify().into_plan();
+    assert_eq!(simplify('a'.complement().complement()), 'a'.re());
+}
+
+#[test]
+fn and() {
+    let anything = || build_plan::Re::Zero.complement();
+    let contains_ab = anything().seq("ab").seq(anything());
+    let even = 'a'.alt('b').seq('a'.alt('b')).star();
+    let r = Regex::from(&contains_ab.and(even));
+    assert!(r.is_match("abab"));
+    assert!(r.is_match("baba"));
+    assert!(!r.is_match("aba"));
+    assert!(!r.is_match("bbbb"));
+
+    let r = Regex::from(&'a'.star().and("aa"));
+    assert_eq!(debug(&r), "Regex('a'*&'a'.'a')");
+    assert!(!r.nullable());
+    assert_eq!(debug(&r.der('a').simp()), "Regex('a'*&'a')");
+    assert_eq!(r.length_bounds(), Some((2, Some(2))));
+    assert_eq!(r.first_set().unwrap().iter().collect::<String>(), "a");
+    assert!(Regex::from(&'a'.and("aa")).matches_nothing());
+    assert_eq!(
+        debug(&Regex::from(&'a'.and('b').seq('c'))),
+        "Regex(('a'&'b').'c')"
+    );
+
+    assert_eq!(debug(&Regex::from(&'a'.and('a')).simp()), "Regex('a')");
+    assert_eq!(
+        debug(&Regex::from(&'a'.and(build_plan::Re::Zero)).simp()),
+        "Regex(0)"
+    );
+    let simplify = |plan: build_plan::Re| plan.transform().simplify().into_plan();
+    assert_eq!(simplify("ab".and("ab")), "ab".re());
+    assert_eq!(
+        simplify('a'.and(build_plan::Re::Zero)),
+        build_plan::Re::Zero
+    );
+}
+
+#[test]
+fn range() {
+    let lower = || build_plan::Re::range('a', 'z');
+    let r = Regex::from(&lower().plus().seq('1'));
+    assert_eq!(debug(&r), "Regex([\'a\'-\'z\']+.\'1\')");
+    assert!(r.is_match("hello1"));
+    assert!(!r.is_match("Hello1"));
+    assert!(!r.is_match("1"));
+    assert!(!Regex::from(&lower()).nullable());
+    assert_eq!(debug(&Regex::from(&lower()).der('q')), "Regex(1)");
+    assert_eq!(debug(&Regex::from(&lower()).der('A')), "Regex(0)");
+    assert!(Regex::from(&lower().seq('x')).contains("12kx"));
+    // every `char` in the range leads to the same state, so only one is listed
+    assert_eq!(Regex::from(&lower().seq('a')).enumerate(2), vec!["aa"]);
+
+    // one node, instead of a chain of 26 `Alt`s
+    assert_eq!(Regex::from(&lower()).alloc().len(), 1);
+
+    // ranges across the surrogate gap never match the surrogates, but do match either side
+    let r = Regex::from(&build_plan::Re::range('\u{D7FF}', '\u{E000}'));
+    assert!(r.is_match("\u{D7FF}"));
+    assert!(r.is_match("\u{E000}"));
+
+    // only ASCII ranges are a single byte range, anything else is spelled out
+    for plan in [lower(), build_plan::Re::range('à', 'ä')] {
+        let bytes = Regex::<Bytes>::from_plan(&plan.clone().plus());
+        let chars = Regex::from(&plan.plus());
+        for s in ["abc", "àä", "aé", "ã", ""] {
+            assert_eq!(
+                bytes.is_match_symbols(s.bytes()),
+                chars.is_match(s),
+                "{:?}",
+                s
+            );
+        }
+    }
+
+    let r = lower().transform().case_fold().compile();
+    assert!(r.is_match("Q"));
+    assert!(!r.is_match("1"));
+}
+
+#[test]
+fn any() {
+    let any = build_plan::Re::any;
+    let r = Regex::from(&any().star());
+    assert_eq!(debug(&r), "Regex(.*)");
+    for s in ["", "a", "hello world", "\n", "\u{10FFFF}é"] {
+        assert!(r.is_match(s), "{:?}", s);
+    }
+    assert!(r.matches_everything());
+    assert!(!Regex::from(&any()).nullable());
+    assert_eq!(debug(&Regex::from(&any()).der('x')), "Regex(1)");
+    assert!(Regex::from(&'a'.and('b')).matches_nothing());
+
+    // `.a` enumerates one representative for the `.`
+    let found = Regex::from(&any().seq('a')).enumerate(2);
+    assert_eq!(found.len(), 1);
+    assert!(found[0].ends_with('a'));
+    assert_eq!(found[0].chars().count(), 2);
+
+    // once `xyz` has been seen, the rest of the input can't change the result, so matching
+    // stops there instead of looking at every (here, infinitely many) remaining chars
+    let r = Regex::from(&any().star().seq("xyz").seq(any().star()));
+    let input = "abxyz".chars().chain(std::iter::repeat('q'));
+    assert!(r.is_match_symbols(input));
+    assert!(r.is_match("__xyz__"));
+    assert!(!r.is_match("__xy__z"));
+
+    // `.` is a whole `char`, even when matching bytes
+    let bytes = Regex::<Bytes>::from_plan(&any().seq('a'));
+    for s in ["xa", "éa", "€a", "🦀a"] {
+        assert!(bytes.is_match_symbols(s.bytes()), "{:?}", s);
+    }
+    assert!(!bytes.is_match_symbols("ééa".bytes()));
+}
+
+#[test]
+fn clone_static() {
+    let plans = [
+        "a(b|c)*d".parse().unwrap(),
+        'a'.plus().and('a'.seq('a').star()).complement(),
+        build_plan::Re::range('a', 'z')
+            .opt()
+            .seq(build_plan::Re::any()),
+        build_plan::Re::Zero,
+    ];
+    for plan in &plans {
+        let r = Regex::from(plan);
+        for r in [r.der('a').simp().clone(), r] {
+            let copy = r.clone_static();
+            assert_eq!(copy, r.clone());
+            assert_eq!(debug(&copy), debug(&r));
+            assert_eq!(copy.alloc().len(), r.alloc().len());
+            // nothing in the copy points back into the original
+            let tree = copy.tree.as_non_null();
+            assert!(r.alloc().index_of(tree).is_none());
+            let inputs = ["", "a", "abd", "bd", "aa", "q"];
+            let expected: Vec<bool> = inputs.iter().map(|s| r.is_match(s)).collect();
+            drop(r);
+            let found: Vec<bool> = inputs.iter().map(|s| copy.is_match(s)).collect();
+            assert_eq!(found, expected);
+        }
+    }
+}
+
+#[test]
+fn nullable_deep() {
+    const DEPTH: usize = 200_000;
+    let one = || build_plan::Re::One;
+    let right = (0..DEPTH).fold(one(), |r, _| "".re().seq(r));
+    assert!(Regex::from(&right).nullable());
+    let left = (0..DEPTH).fold(one(), |r, _| r.seq('a'.star()));
+    assert!(Regex::from(&left).nullable());
+    let left = (0..DEPTH).fold(one(), |r, _| r.alt('a')).seq('b');
+    assert!(!Regex::from(&left).nullable());
+    let nots = (0..DEPTH + 1).fold(one(), |r, _| r.complement());
+    assert!(!Regex::from(&nots).nullable());
+    let pluses = (0..DEPTH).fold('a'.re(), |r, _| r.plus().alt(build_plan::Re::Zero));
+    assert!(!Regex::from(&pluses).nullable());
+}
+
+#[test]
+fn simp_star() {
+    let simp = |plan: build_plan::Re| debug(&Regex::from(&plan).simp());
+    assert_eq!(simp('a'.star().star().star()), "Regex('a'*)");
+    assert_eq!(simp(build_plan::Re::Zero.star()), "Regex(1)");
+    assert_eq!(simp("".re().star()), "Regex(1)");
+    assert_eq!(simp('a'.alt(build_plan::Re::Zero).star()), "Regex('a'*)");
+    assert_eq!(simp(build_plan::Re::Zero.star().seq('a')), "Regex('a')");
+
+    // unchanged stars are reused, not reallocated
+    let r = Regex::from(&'a'.seq('b').star());
+    assert_eq!(r.simp().alloc().len(), 0);
+
+    // derivatives of nested stars don't keep growing
+    let r = Regex::from(&'a'.star().star().star());
+    let d = r.der_str(&"a".repeat(100));
+    assert_eq!(debug(&d), "Regex('a'*)");
+}
+
+#[test]
+fn simp_alt_canonical() {
+    let simp = |plan: build_plan::Re| debug(&Regex::from(&plan).simp());
+    let right = 'a'.alt('b'.alt('c'));
+    let left = 'a'.alt('b').alt('c');
+    assert_eq!(simp(left), simp(right.clone()));
+    assert_eq!(simp(right), "Regex('a'|'b'|'c')");
+    assert_eq!(
+        simp('a'.alt('b').alt('c'.alt('d')).alt('e')),
+        "Regex('a'|'b'|'c'|'d'|'e')"
+    );
+
+    // duplicates are dropped wherever they are, keeping the last one
+    assert_eq!(simp('a'.alt('b').alt('a')), "Regex('b'|'a')");
+    assert_eq!(simp('a'.alt('b'.alt('a'))), "Regex('b'|'a')");
+    assert_eq!(
+        simp('a'.alt(build_plan::Re::Zero).alt('b'.alt('a'))),
+        "Regex('b'|'a')"
+    );
+
+    // already canonical, so nothing is allocated
+    let r = Regex::from(&'a'.alt('b'.alt('c')));
+    assert_eq!(r.simp().alloc().len(), 0);
+
+    // the states of (a|b)*(a|b)* don't keep growing as they are derived
+    let r = Regex::from(&'a'.alt('b').star().seq('a'.alt('b').star()));
+    let short = r.der_str("ab").alloc().len();
+    assert!(r.der_str(&"ab".repeat(50)).alloc().len() <= short);
+}
+
+#[test]
+fn simp_wide_alt() {
+    // simplifying used to scan the spine again at every level, which took seconds for this
+    let chars: Vec<char> = (0..8000)
+        .map(|i| char::from_u32(0x4e00 + i).unwrap())
+        .collect();
+    let alts = |chars: &[char]| {
+        let last = build_plan::Re::Char(*chars.last().unwrap());
+        chars.iter().rev().skip(1).fold(last, |r, &c| c.alt(r))
+    };
+    let r = Regex::from(&alts(&chars));
+    assert_eq!(r.simp().alloc().len(), 0);
+
+    // only the branches in front of the duplicate are rebuilt
+    let with_dup = Regex::from(&chars[0].alt(alts(&chars)));
+    assert_eq!(with_dup.simp(), r);
+    assert_eq!(with_dup.simp().alloc().len(), 0);
+    // and a nested alternation is spliced into one spine
+    let d = Regex::from(&'x'.alt(alts(&chars[1..])).alt(chars[0]));
+    assert_eq!(d.simp().node_count(), 2 * chars.len() + 1);
+
+    // and matching simplifies once for every `char`
+    let r = Regex::from(&alts(&chars[..1000]));
+    assert!(r.is_match(&chars[123].to_string()));
+    assert!(!r.is_match("abcde"));
+}
+
+#[test]
+fn matcher() {
+    let r = Regex::from(&"ab".plus());
+    let mut m = Matcher::new(&r);
+    assert!(!m.is_accepting());
+    let mut accepting = Vec::new();
+    for c in "abab".chars() {
+        m.feed(c);
+        accepting.push(m.is_accepting());
+    }
+    assert_eq!(accepting, [false, true, false, true]);
+    assert!(!m.is_dead());
+
+    // an infinite stream can be abandoned as soon as it can't match any more
+    let mut m = Matcher::new(&r);
+    let mut fed = 0;
+    for c in "abac".chars().chain(std::iter::repeat('a')) {
+        m.feed(c);
+        fed += 1;
+        if m.is_dead() {
+            break;
+        }
+    }
+    assert_eq!(fed, 4);
+    assert!(!m.is_accepting());
+
+    let r = Regex::from(&"ab".re()).with_char_normalizer(|c| c.to_ascii_lowercase());
+    let mut m = Matcher::new(&r);
+    m.feed('A');
+    m.feed('B');
+    assert!(m.is_accepting());
+    assert_eq!(debug(m.state()), "Regex(1)");
+}
+
+#[test]
+fn ders_stop_when_dead() {
+    // dies on the second char, so nothing after the first chunk of four is even pulled
+    let pulled = std::cell::Cell::new(0);
+    let r = Regex::from(&"abcdefgh".re());
+    let input = "axcdefgh".chars().inspect(|_| pulled.set(pulled.get() + 1));
+    assert!(!r.is_match_symbols(input));
+    assert_eq!(pulled.get(), 4);
+    assert_eq!(debug(&r.der_str("ax")), "Regex(0)");
+    assert_eq!(debug(&r.der_str("axcd")), "Regex(0)");
+    assert_eq!(debug(&r.der_str("axcdefgh")), "Regex(0)");
+    assert!(r.is_match("abcdefgh"));
+}
+
+#[test]
+fn der_many() {
+    // the `a^10000 b` workload
+    let input: Vec<char> = "a".repeat(10_000).chars().chain(['b']).collect();
+    let r = Regex::from(&'a'.star().seq('b'));
+    let many = r.der_many(&input);
+    let one_at_a_time = r.der_str(&input.iter().collect::<String>());
+    assert!(many.nullable());
+    assert_eq!(many, one_at_a_time);
+    assert_eq!(debug(&many), "Regex(1)");
+
+    for (plan, input) in [
+        ("(ab|a)*b".parse().unwrap(), "abaab"),
+        ('a'.alt('b').star().seq('a').seq('a'.alt('b')), "bbbbab"),
+        ('a'.alt('b').star().seq('a').seq('a'.alt('b')), "bbbbba"),
+        ("ab".re(), "ax"),
+        ("ab".re(), ""),
+    ] {
+        let r = Regex::from(&plan);
+        let cs: Vec<char> = input.chars().collect();
+        let stepped = cs.iter().fold(r.clone(), |d, c| d.der(*c).simp().clone());
+        assert_eq!(r.der_many(&cs), stepped, "{:?} on {:?}", r, input);
+    }
+
+    // states that grow past the allocator are carried over into a bigger one
+    let wide = (0..200).fold(build_plan::Re::Zero, |r, i| {
+        r.alt(char::from_u32('a' as u32 + i % 26).unwrap().seq('z'.star()))
+    });
+    let r = Regex::from(&wide.star());
+    let cs: Vec<char> = "abzzcz".repeat(50).chars().collect();
+    assert!(r.der_many(&cs).nullable());
+}
+
+#[test]
+fn matcher_cached() {
+    let r = Regex::from(&"ab".alt("ac").star());
+    let mut m = r.matcher_cached();
+    for s in ["", "ab", "abac", "aba", "abad", "acacab", "ba"] {
+        assert_eq!(m.is_match(s.chars()), r.is_match(s), "{:?}", s);
+    }
+    // every state has been seen by now, so matching again doesn't add any
+    let states = m.cache().len();
+    for s in ["abababacacac", "abacabacx", "acab"] {
+        assert_eq!(m.is_match(s.chars()), r.is_match(s), "{:?}", s);
+    }
+    assert_eq!(m.cache().len(), states);
+
+    let r = Regex::from(&"ab".re()).with_char_normalizer(|c| c.to_ascii_lowercase());
+    assert!(r.matcher_cached().is_match("AB".chars()));
+}
+
+#[test]
+fn canonical_hash_and_ord() {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+    fn hash(r: &Regex) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        r.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // the same tree, once built directly and once as a derivative
+    let r = Regex::from(&"b".seq('c'.star()));
+    let d = Regex::from(&"ab".seq('c'.star()));
+    let d = d.der('a');
+    let d = d.simp();
+    assert_eq!(d, r);
+    assert_eq!(hash(&d), hash(&r));
+    assert_eq!(d.cmp(&r), std::cmp::Ordering::Equal);
+
+    let mut rs: Vec<Regex> = ["b", "a", "ab", "a", ""]
+        .into_iter()
+        .map(|s| Regex::from(&s.re()))
+        .collect();
+    rs.sort();
+    rs.dedup();
+    assert_eq!(
+        rs.iter().map(debug).collect::<Vec<_>>(),
+        ["Regex(1)", "Regex('a')", "Regex('b')", "Regex('a'.'b')",]
+    );
+    assert!(Regex::from(&"a".re()) < Regex::from(&"a".star()));
+}
+
+#[test]
+fn from_pattern() {
+    struct Holder {
+        r: Regex<'static>,
+    }
+    let h = Holder {
+        r: Regex::from_pattern("(ab|c)+d?").unwrap(),
+    };
+    assert!(h.r.is_match("abcd"));
+    assert!(!h.r.is_match("abd d"));
+
+    let r = Regex::from_pattern("\\bcat\\b").unwrap();
+    assert!(r.contains("a cat!"));
+    assert!(!r.contains("concatenate"));
+
+    let err = Regex::from_pattern("a(b").unwrap_err();
+    assert_eq!(err.kind, parse::ParseErrorKind::UnclosedParen);
+}
+
+#[test]
+fn bounded_repetition() {
+    let matches = |plan: &build_plan::Re| {
+        let r = Regex::from(plan);
+        (0..7)
+            .filter(|&n| r.is_match(&"a".repeat(n)))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!('a'.repeat_exact(0), build_plan::Re::One);
+    assert_eq!(matches(&'a'.repeat_exact(0)), [0]);
+    assert_eq!(matches(&'a'.repeat_exact(3)), [3]);
+
+    assert_eq!('a'.repeat_at_least(0), 'a'.star());
+    assert_eq!(matches(&'a'.repeat_at_least(2)), [2, 3, 4, 5, 6]);
+
+    assert_eq!(matches(&'a'.repeat_range(0, 0)), [0]);
+    assert_eq!(matches(&'a'.repeat_range(0, 2)), [0, 1, 2]);
+    assert_eq!(matches(&'a'.repeat_range(2, 2)), [2]);
+    assert_eq!(matches(&'a'.repeat_range(2, 4)), [2, 3, 4]);
+    assert_eq!('a'.repeat_range(3, 2), build_plan::Re::Zero);
+    assert_eq!(matches(&'a'.repeat_range(3, 2)), Vec::<usize>::new());
+
+    let digits = build_plan::Re::range('0', '9').repeat_exact(3);
+    let r = Regex::from(&digits);
+    assert!(r.is_match("042"));
+    assert!(!r.is_match("42"));
+    assert!(!r.is_match("4a2"));
+    assert_eq!('a'.repeat_range(1, 3).to_string(), "a(aa?)?");
+}
+
+#[test]
+fn find() {
+    let r = Regex::from(&"ab".alt('a').plus());
+    assert_eq!(r.find("xxabababa!"), Some((2, 9)));
+    assert_eq!(r.find("xyz"), None);
+
+    // offsets are in bytes, not chars
+    let r = Regex::from(&"é".seq('b'.star()));
+    assert_eq!(r.find("ßéébb"), Some((2, 4)));
+    let r = Regex::from(&"éb".re());
+    assert_eq!(r.find("ßéébb"), Some((4, 7)));
+
+    // an empty match at the very start is still the leftmost
+    let r = Regex::from(&'a'.star());
+    assert_eq!(r.find("baa"), Some((0, 0)));
+    assert_eq!(r.find(""), Some((0, 0)));
+
+    let r = Regex::from_pattern("\\bcat\\b").unwrap();
+    assert_eq!(r.find("concat cat"), Some((7, 10)));
+}
+
+#[test]
+fn find_iter() {
+    let r = Regex::from(&'a'.alt('b').plus());
+    let s = "ab-ba--a-bbbbaaab-".repeat(20);
+    let found: Vec<_> = r.find_iter(&s).collect();
+    assert_eq!(found.len(), 80);
+    assert_eq!(found[..4], [(0, 2), (3, 5), (7, 8), (9, 17)]);
+    for (start, end) in found {
+        assert!(r.is_match(&s[start..end]));
+    }
+
+    // empty matches are found between every char, including after the last one
+    let r = Regex::from(&'a'.star());
+    assert_eq!(
+        r.find_iter("baaé").collect::<Vec<_>>(),
+        [(0, 0), (1, 3), (3, 3), (5, 5)]
+    );
+    assert_eq!(r.find_iter("").collect::<Vec<_>>(), [(0, 0)]);
+}
+
+#[test]
+fn fold_case() {
+    let r = Regex::from(&"Hello".fold_case());
+    for s in ["Hello", "hello", "HELLO", "hElLo"] {
+        assert!(r.is_match(s), "{:?}", s);
+    }
+    assert!(!r.is_match("Help"));
+
+    // characters without case are left alone
+    assert_eq!("1-".fold_case(), "1-".re());
+
+    // 'ß' only has a multi-char upper case, so it only matches itself and its single-char
+    // capital 'ẞ' (which lower cases back to it)
+    let r = Regex::from(&'ß'.fold_case());
+    assert!(r.is_match("ß"));
+    assert!(!r.is_match("SS"));
+    let r = Regex::from(&'ẞ'.fold_case());
+    assert!(r.is_match("ß"));
+    assert!(r.is_match("ẞ"));
+}
+
+#[test]
+fn any_of() {
+    let vowels = || build_plan::Re::one_of("uoiea".chars());
+    assert_eq!(vowels(), build_plan::Re::one_of("aeiouu".chars()));
+    assert_eq!(vowels().to_string(), "[aeiou]");
+
+    let r = Regex::from(&vowels().plus());
+    assert_eq!(debug(&r), "Regex(['a' 'e' 'i' 'o' 'u']+)");
+    // one node for the set, and one per `char`, instead of a chain of `Alt`s
+    assert_eq!(Regex::from(&vowels()).alloc().len(), 6);
+    for c in 'a'..='z' {
+        let d = Regex::from(&vowels()).der(c).nullable();
+        assert_eq!(d, "aeiou".contains(c), "{:?}", c);
+    }
+    assert!(r.is_match("aeeiou"));
+    assert!(!r.is_match("ab"));
+    assert!(!r.is_match(""));
+    assert_eq!(r.first_set().unwrap().len(), 5);
+
+    let plan = vowels().seq('b'.alt(vowels()).star());
+    let r = Regex::from(&plan);
+    for s in all_strings(&['a', 'b', 'c', 'u'], 4) {
+        let cs: Vec<char> = s.chars().collect();
+        assert_eq!(
+            r.is_match(&s),
+            naive_ends(&plan, &cs, 0).contains(&cs.len()),
+            "{:?}",
+            s
+        );
+    }
+
+    // copies keep the set together
+    assert_eq!(r.clone(), r);
+    assert_eq!(r.clone().clone_static(), r);
+    assert!(r.clone().clone_static().is_match("ebu"));
+
+    // a `char` that isn't a single byte can't go in a byte set, so the set is spelled out
+    let plan = build_plan::Re::one_of(['a', 'é', 'z']).plus();
+    let bytes = Regex::<Bytes>::from_plan(&plan);
+    let chars = Regex::from(&plan);
+    for s in ["aéz", "e", "é", "zz", ""] {
+        assert_eq!(
+            bytes.is_match_symbols(s.bytes()),
+            chars.is_match(s),
+            "{:?}",
+            s
+        );
+    }
+    let bytes = Regex::<Bytes>::from_plan(&vowels());
+    assert!(bytes.is_match_symbols("o".bytes()));
+
+    let r = vowels().fold_case();
+    assert_eq!(r, build_plan::Re::one_of("aeiouAEIOU".chars()));
+    assert_eq!(
+        Regex::from(&build_plan::Re::one_of([])),
+        Regex::from(&build_plan::Re::Zero)
+    );
+}
+
+#[test]
+fn not_any_of() {
+    let r = Regex::from(&build_plan::Re::none_of(['a']));
+    assert_eq!(debug(&r), "Regex([^'a'])");
+    assert_eq!(build_plan::Re::none_of("ba".chars()).to_string(), "[^ab]");
+    assert!(r.is_match("b"));
+    assert!(r.is_match("\n"));
+    assert!(!r.is_match("a"));
+    assert!(!r.is_match(""));
+    assert!(!r.is_match("bb"));
+    assert_eq!(r.first_set(), None);
+
+    let plan = build_plan::Re::none_of("0123456789".chars()).plus();
+    let r = Regex::from(&plan);
+    assert_eq!(r.find("123abc45"), Some((3, 6)));
+    for s in all_strings(&['a', '1', 'é'], 3) {
+        let cs: Vec<char> = s.chars().collect();
+        assert_eq!(
+            r.is_match(&s),
+            naive_ends(&plan, &cs, 0).contains(&cs.len()),
+            "{:?}",
+            s
+        );
+    }
+    assert_eq!(r.clone().clone_static(), r);
+
+    // a byte isn't a whole `char`, so the set is spelled out
+    let bytes = Regex::<Bytes>::from_plan(&plan);
+    for s in ["ab", "é", "a1", "", "éé"] {
+        assert_eq!(bytes.is_match_symbols(s.bytes()), r.is_match(s), "{:?}", s);
+    }
+
+    let r = build_plan::Re::none_of(['a']).fold_case();
+    assert_eq!(r, build_plan::Re::none_of(['a', 'A']));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trip() {
+    let plan = "ab"
+        .alt(build_plan::Re::range('0', '9').plus())
+        .seq(build_plan::Re::one_of("xyz".chars()).opt())
+        .seq(build_plan::Re::none_of(['q']).star().and('c'.complement()))
+        .seq(build_plan::Re::any());
+    let json = serde_json::to_string(&plan).unwrap();
+    let back: build_plan::Re = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, plan);
+
+    let (r, back) = (Regex::from(&plan), Regex::from(&back));
+    assert_eq!(r, back);
+    for s in ["ab!", "12x!", "abzab!", "a"] {
+        assert_eq!(r.is_match(s), back.is_match(s), "{:?}", s);
+    }
+}
+
+#[test]
+fn der_reserves_up_front() {
+    let words = [
+        "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "theta", "iota",
+    ];
+    let mut plan = build_plan::Re::Zero;
+    for w in words {
+        plan = plan.alt(w.re().seq(build_plan::Re::range('0', '9').star()));
+    }
+    let r = Regex::from(&plan.seq(' ').star());
+    let input = "alpha12 beta gamma3 delta45 epsilon zeta6 theta iota7 ".repeat(30);
+
+    // this used to restart 243 times, with a fixed starting capacity
+    RESTARTS.with(|n| n.set(0));
+    assert!(r.is_match(&input));
+    assert_eq!(RESTARTS.with(|n| n.get()), 0);
+}
+
+#[test]
+fn node_array() {
+    let r = Regex::from(&"ab".alt('c').star());
+    let nodes: Vec<String> = r.alloc().iter().map(|r| format!("{:?}", r)).collect();
+    // children are always allocated before their parents
+    assert_eq!(
+        nodes,
+        [
+            "'a'",
+            "'b'",
+            "'a'.'b'",
+            "'c'",
+            "'a'.'b'|'c'",
+            "('a'.'b'|'c')*",
+        ]
+    );
+    assert_eq!(r.alloc().as_slice().len(), 6);
+}
+
+#[test]
+// leaving the parentheses out is the point
+#[allow(clippy::precedence)]
+fn operators() {
+    assert_eq!(('a'.re() + "bc") | 'd', "a".seq("bc").alt('d'));
+    // `+` binds more tightly than `|`, like sequencing does in a pattern
+    assert_eq!('a'.re() + 'b' | 'c', 'a'.seq('b').alt('c'));
+    assert_eq!('a'.re() | 'b' + 'c'.re(), 'a'.alt('b'.seq('c')));
+    assert_eq!(
+        "ab" + 'c'.star() | 'd'.plus(),
+        "ab".seq('c'.star()).alt('d'.plus())
+    );
+
+    let r = Regex::from(&(("ab" + 'c'.star()) | 'd'.plus()));
+    assert!(r.is_match("abccc"));
+    assert!(r.is_match("dd"));
+    assert!(!r.is_match("abd"));
+}
+
+#[test]
+fn times() {
+    let r = Regex::from(&'a'.times(1000));
+    // shared, instead of 1000 `Char`s and 999 `Seq`s
+    assert!(r.alloc().len() < 30, "{}", r.alloc().len());
+    assert!(r.is_match(&"a".repeat(1000)));
+    assert!(!r.is_match(&"a".repeat(999)));
+    assert!(!r.is_match(&"a".repeat(1001)));
+    assert_eq!(r.length_bounds(), Some((1000, Some(1000))));
+
+    for n in 0..10 {
+        let plan = "ab".alt('c').times(n);
+        let r = Regex::from(&plan);
+        for s in all_strings(&['a', 'b', 'c'], 6) {
+            let cs: Vec<char> = s.chars().collect();
+            assert_eq!(
+                r.is_match(&s),
+                naive_ends(&plan, &cs, 0).contains(&cs.len()),
+                "{} {:?}",
+                n,
+                s
+            );
+        }
+    }
+    assert_eq!(Regex::from(&'a'.times(0)), Regex::from(&"".re()));
+    assert_eq!("ab".alt('c').times(3).to_string(), "(ab|c){3}");
+}
+
+#[test]
+fn full_and_partial_match() {
+    let r = Regex::from(&"abc".re());
+    assert!(r.is_full_match("abc"));
+    assert!(!r.is_full_match("xabcx"));
+    assert!(!r.is_full_match("abcx"));
+    assert!(r.is_partial_match("abc"));
+    assert!(r.is_partial_match("xabcx"));
+    assert!(!r.is_partial_match("xabx"));
+    assert_eq!(r.is_match("xabcx"), r.is_full_match("xabcx"));
+
+    // a pattern that matches the empty string partially matches anything
+    let r = Regex::from(&'a'.star());
+    assert!(r.is_partial_match("bbb"));
+    assert!(!r.is_full_match("bbb"));
+}
+
+#[test]
+fn replace() {
+    let r = Regex::from(&'a'.alt('b').plus());
+    assert_eq!(r.replace("xxabba-ba", "[$0]"), "xx[$0]-ba");
+    assert_eq!(r.replace_all("xxabba-ba", "_"), "xx_-_");
+    assert_eq!(r.replace_all("xyz", "_"), "xyz");
+    assert_eq!(r.replace("", "_"), "");
+
+    // offsets are in bytes, so multi-byte chars around a match are kept whole
+    let r = Regex::from(&"é".plus());
+    assert_eq!(r.replace_all("ßééxé€", "e"), "ßexe€");
+
+    // empty matches are replaced once each, without looping
+    let r = Regex::from(&'a'.star());
+    assert_eq!(r.replace_all("bab", "-"), "-b--b-");
+    assert_eq!(r.replace("bab", "-"), "-bab");
+    assert_eq!(r.replace_all("", "-"), "-");
+}
+
+#[test]
+fn split() {
+    let r = Regex::from(&','.alt(';').plus());
+    let split = |s| r.split(s).collect::<Vec<_>>();
+    assert_eq!(split("a,b;;c,;d"), ["a", "b", "c", "d"]);
+    assert_eq!(split(",a,b;"), ["", "a", "b", ""]);
+    assert_eq!(split("abc"), ["abc"]);
+    assert_eq!(split(""), [""]);
+    assert_eq!(split("é;ß"), ["é", "ß"]);
+
+    let r = Regex::from(&"".re());
+    assert_eq!(r.split("ab").collect::<Vec<_>>(), ["", "a", "b", ""]);
+
+    // the pieces borrow from the text, not from the `Regex`
+    let text = String::from("a,b");
+    let pieces: Vec<&str> = Regex::from_pattern(",").unwrap().split(&text).collect();
+    assert_eq!(pieces, ["a", "b"]);
+}
+
+#[test]
+fn empty_language() {
+    let empty = |plan: &build_plan::Re| Regex::from(plan).is_empty_language();
+    assert!(empty(&build_plan::Re::Zero));
+    assert!(empty(&'a'.and('a'.complement())));
+    assert!(empty(
+        &'a'.alt('b').star().complement().and('a'.alt('b').star())
+    ));
+    assert!(empty(&'a'.and("aa")));
+    assert!(empty(
+        &'a'.seq(build_plan::Re::Zero).alt(build_plan::Re::Zero)
+    ));
+
+    assert!(!empty(&"".re()));
+    assert!(!empty(&'a'.star()));
+    assert!(!empty(&'a'.alt('b').and('a')));
+    assert!(!empty(&'a'.complement()));
+
+    // derivatives can empty out too
+    assert!(Regex::from(&"ab".re()).der('b').is_empty_language());
+
+    assert!(Regex::from(&'a'.star()).matches_empty_string());
+    assert!(Regex::from(&"".re()).matches_empty_string());
+    assert!(!Regex::from(&'a'.plus()).matches_empty_string());
+    assert!(!Regex::from(&build_plan::Re::Zero).matches_empty_string());
+}
+
+#[test]
+fn simp_full() {
+    let zero = || build_plan::Re::Zero;
+    let r = Regex::from(&zero().alt(zero().alt(zero().alt('a'))));
+    assert_eq!(r.simp_full(), Regex::from(&'a'.re()));
+    let r = Regex::from(&'a'.complement().complement().star().star().and('a'.star()));
+    assert_eq!(r.simp_full(), Regex::from(&'a'.star()));
+
+    for plan in all_patterns(2) {
+        let r = Regex::from(&plan).simp_full();
+        assert_eq!(r.simp(), r, "{plan}");
+    }
+}
+
+#[test]
+fn node_count_and_depth() {
+    let plan = "abc".re().alt('d'.star());
+    assert_eq!((plan.node_count(), plan.depth()), (8, 4));
+    let r = Regex::from(&plan);
+    assert_eq!((r.node_count(), r.depth()), (8, 4));
+
+    let plan = 'a'.re();
+    assert_eq!((plan.node_count(), plan.depth()), (1, 1));
+    assert_eq!(Regex::from(&plan).node_count(), 1);
+
+    // the set is allocated along with the node
+    let r = Regex::from(&build_plan::Re::one_of("aeiou".chars()));
+    assert_eq!((r.node_count(), r.depth()), (6, 1));
+
+    // shared subtrees count once, but are as deep as they would be unshared
+    let plan = 'a'.times(1024);
+    assert_eq!((plan.node_count(), plan.depth()), (2, 2));
+    let r = Regex::from(&plan);
+    assert_eq!((r.node_count(), r.depth()), (11, 11));
+
+    // simp shrinks derivatives
+    let r = Regex::from(&"ab".re().alt("ac").star());
+    let d = r.der('a');
+    assert!(d.simp().node_count() < d.node_count());
+
+    // deep trees don't overflow the stack
+    let long = "a".repeat(100_000).re();
+    assert_eq!(long.depth(), 100_000);
+    assert_eq!(Regex::from(&long).node_count(), 199_999);
+}
+
+#[test]
+fn clone_outlives_source() {
+    let r = Regex::from(&"ab".re().alt('a'.seq('c'.star())));
+    let (d, owned) = {
+        let d = r.der('a');
+        let owned = d.simp().to_owned();
+        (d.clone(), owned)
+    };
+    // the clones own every node, none of them point into `r`
+    for copy in [&d, &owned] {
+        let tree = copy.tree.as_non_null();
+        assert!(r.alloc().index_of(tree).is_none());
+        assert!(copy.alloc().index_of(tree).is_some());
+    }
+
+    // moving and dropping the source doesn't affect them
+    let moved = Box::new(r);
+    drop(moved);
+    for copy in [d, owned] {
+        assert!(copy.is_match("b"));
+        assert!(copy.is_match("ccc"));
+        assert!(copy.is_match(""));
+        assert!(!copy.is_match("a"));
+    }
+}
+
+#[test]
+fn empty_input() {
+    let computed = || analysis::ANALYSES_COMPUTED.with(|n| n.get());
+    let cases = [
+        ("".re(), true),
+        (build_plan::Re::Zero, false),
+        ('a'.star(), true),
+        ('a'.re(), false),
+    ];
+    for (plan, expected) in &cases {
+        let r = Regex::from(plan);
+        let before = computed();
+        assert_eq!(r.is_match(""), *expected, "{plan}");
+        assert_eq!(r.is_match_symbols([]), *expected, "{plan}");
+        // answered from the tree alone
+        assert_eq!(computed(), before);
+    }
+
+    // a dead state stays dead, however much input is left
+    let r = Regex::from(&"ab".re());
+    let dead = r.der_str("bab");
+    assert_eq!(dead, Regex::from(&build_plan::Re::Zero));
+    assert!(!dead.nullable());
+    assert!(!r.is_match("bab"));
+}
+
+#[test]
+fn is_match_chars() {
+    let strings = all_strings(&['a', 'b'], 3);
+    for plan in all_patterns(1) {
+        let r = Regex::from(&plan);
+        for s in &strings {
+            let chars: Vec<char> = s.chars().collect();
+            assert_eq!(r.is_match_chars(&chars), r.is_match(s), "{plan} on {s:?}");
+            assert_eq!(r.is_match_iter(chars), r.is_match(s), "{plan} on {s:?}");
+        }
+    }
+
+    // stops at the first `char` that can't match, so this doesn't run forever
+    let r = Regex::from(&'a'.star());
+    assert!(!r.is_match_iter(std::iter::once('x').chain(std::iter::repeat('a'))));
+
+    // the boundaries are checked against the first and last `char`
+    let r = Regex::from_pattern("\\bcat\\b").unwrap();
+    let chars: Vec<char> = "cat".chars().collect();
+    assert!(r.is_match_chars(&chars));
+    let r = Regex::from_pattern("\\Bcat").unwrap();
+    assert!(!r.is_match_chars(&chars));
+    let r = Regex::from_pattern("a.*\\b").unwrap();
+    assert!(r.is_match_chars(&['a', 'b']));
+    assert!(!r.is_match_chars(&['a', 'b', ' ']));
+}
+
+#[test]
+fn shrink_to_fit() {
+    let r = Regex::from_pattern("(a|b)*a(a|b)(a|b)").unwrap();
+    let mut d = r.der_str(&"ab".repeat(100));
+    // a child never owns anything, so there is nothing to free
+    assert_eq!(d.child().shrink_to_fit(), 0);
+
+    let before = d.alloc().capacity();
+    let freed = d.shrink_to_fit();
+    assert!(freed > 0);
+    assert_eq!(d.alloc().capacity(), before - freed);
+    assert_eq!(d, r.der_str(&"ab".repeat(100)));
+    for s in all_strings(&['a', 'b'], 4) {
+        assert_eq!(
+            d.is_match(&s),
+            r.is_match(&format!("{}{s}", "ab".repeat(100)))
+        );
+    }
+    // already as small as it gets
+    assert_eq!(d.shrink_to_fit(), 0);
+}
+
+#[test]
+fn captures() {
+    // the first group can match empty
+    let r = Regex::from(&'a'.alt("").mark(1).seq('b'.mark(2)));
+    assert_eq!(r.captures("ab"), Some([(1, 0), (2, 1)].into()));
+    assert_eq!(r.captures("b"), Some([(1, 0), (2, 0)].into()));
+    assert_eq!(r.captures("a"), None);
+
+    // marks that the match doesn't go through are left out
+    let r = Regex::from(&'a'.mark(1).alt('b'.mark(2)).seq('c'.mark(3).opt()));
+    assert_eq!(r.captures("a"), Some([(1, 0)].into()));
+    assert_eq!(r.captures("bc"), Some([(2, 0), (3, 1)].into()));
+    let r = Regex::from(&'a'.mark(1).complement().seq('b'.mark(2)));
+    assert_eq!(r.captures("xyb"), Some([(2, 2)].into()));
+
+    // a repeated mark is where it was first entered, and offsets are in bytes
+    let r = Regex::from(&"é".re().seq('x'.mark(1).seq('y'.opt()).plus()));
+    assert_eq!(r.captures("éxyxx"), Some([(1, 2)].into()));
+    let r = Regex::from(&'a'.mark(1).star().mark(2).seq('b'));
+    assert_eq!(r.captures("b"), Some([(2, 0)].into()));
+
+    // splits are the longest first part that lets the rest match
+    let r = Regex::from(&'a'.star().mark(1).seq('a'.star().mark(2)));
+    assert_eq!(r.captures("aaa"), Some([(1, 0), (2, 3)].into()));
+
+    // marks don't change what matches
+    let r = Regex::from(&"ab".re().mark(7).star());
+    assert!(r.is_match("abab"));
+    assert!(!r.is_match("aba"));
+    assert_eq!(debug(&r), "Regex(<7:'a'.'b'>*)");
+    assert_eq!("ab".re().mark(7).star().to_string(), "<7:ab>*");
+    assert_ne!(r, Regex::from(&"ab".re().mark(8).star()));
+    assert_ne!(r, Regex::from(&"ab".re().star()));
+}
+
+#[test]
+fn captures_exhaustive() {
+    let inputs = all_strings(&['a', 'b'], 3);
+    for p1 in all_patterns(1) {
+        for p2 in all_patterns(1) {
+            let r = Regex::from(&p1.clone().mark(1).seq(p2.clone().mark(2)));
+            for s in &inputs {
+                let chars: Vec<char> = s.chars().collect();
+                let split = (0..=chars.len()).rev().find(|&k| {
+                    naive_ends(&p1, &chars, 0).contains(&k)
+                        && naive_ends(&p2, &chars, k).contains(&chars.len())
+                });
+                let expected = split.map(|k| [(1, 0), (2, k)].into());
+                assert_eq!(r.captures(s), expected, "{p1} {p2} {s:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn is_match_counting() {
+    let r = Regex::from(&'a'.star().seq('b'));
+    assert_eq!(r.is_match_counting("aaab"), (true, 4));
+    assert_eq!(r.is_match_counting(""), (false, 0));
+    // dead after the first char, so the rest isn't derived
+    assert_eq!(r.is_match_counting("baaaa"), (false, 2));
+    assert_eq!(r.is_match_counting("caaaa"), (false, 1));
+    // matches everything after the first char
+    let r = Regex::from(&'a'.seq(build_plan::Re::any().star()));
+    assert_eq!(r.is_match_counting("abcdef"), (true, 1));
+
+    for plan in all_patterns(1) {
+        let r = Regex::from(&plan);
+        for s in all_strings(&['a', 'b'], 3) {
+            assert_eq!(r.is_match_counting(&s).0, r.is_match(&s), "{plan} {s:?}");
+        }
+    }
+}
+
+#[test]
+fn try_from_plan() {
+    let plan = "a(b|c)*d".parse().unwrap();
+    let r = Regex::try_from_plan(&plan).unwrap();
+    assert_eq!(r, Regex::from(&plan));
+    assert!(r.is_match("abcd"));
+
+    // a tiny limit, and one that is just enough
+    assert_eq!(
+        Regex::<Chars>::try_from_plan_with_limit(&plan, 4).unwrap_err(),
+        BuildError::CapacityExceeded { limit: 4 }
+    );
+    let size = Regex::from(&plan).node_count();
+    let r = Regex::<Chars>::try_from_plan_with_limit(&plan, size).unwrap();
+    assert!(r.alloc().capacity() <= size);
+    assert!(r.is_match("abbd"));
+    assert!(Regex::<Chars>::try_from_plan_with_limit(&plan, 0).is_err());
+
+    // plans that skip the checks in the constructors
+    let bad = [
+        (
+            build_plan::Re::Range('z', 'a'),
+            BuildError::InvalidRange('z', 'a'),
+        ),
+        (
+            'x'.seq(build_plan::Re::AnyOf(vec!['b', 'a'])),
+            BuildError::UnsortedSet,
+        ),
+        (
+            build_plan::Re::NotAnyOf(vec!['a', 'a']).star(),
+            BuildError::UnsortedSet,
+        ),
+    ];
+    for (plan, e) in &bad {
+        assert_eq!(
+            Regex::<Chars>::try_from_plan(plan).unwrap_err(),
+            *e,
+            "{plan}"
+        );
+    }
+
+    // the pattern side reports where it went wrong
+    assert_eq!(Regex::try_from("a(b").unwrap_err().offset, 1);
+    assert!(Regex::try_from("a(b)").unwrap().is_match("ab"));
+}
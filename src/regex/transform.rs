@@ -0,0 +1,184 @@
+use super::build_plan::{self, take, ImplicitRe, Re};
+use super::Regex;
+
+/// Applies a series of rewrites to a `build_plan::Re` before it is built, e.g.
+/// `plan.transform().case_fold().simplify().compile()`. Each rewrite preserves the language of the
+/// plan, except for `case_fold` and `reverse`, which change it on purpose.
+pub struct PlanTransform {
+    plan: Re,
+}
+
+impl build_plan::Re {
+    pub fn transform(self) -> PlanTransform {
+        PlanTransform { plan: self }
+    }
+}
+
+impl PlanTransform {
+    fn rewrite(self, f: impl FnMut(Re) -> Re) -> Self {
+        Self {
+            plan: self.plan.rewrite(f),
+        }
+    }
+
+    /// Makes every `Char`, `Range` and `AnyOf` match its upper and lower case forms as well. Only
+    /// case mappings to a single `char` are used, so e.g. `'ß'` does not match `"SS"`.
+    pub fn case_fold(self) -> Self {
+        self.rewrite(|r| match r {
+            Re::Char(c) => {
+                let mut cases = vec![c];
+                for d in other_cases(c) {
+                    if !cases.contains(&d) {
+                        cases.push(d);
+                    }
+                }
+                let mut cases = cases.into_iter();
+                let first = cases.next().unwrap().re();
+                cases.fold(first, |r, c| r.alt(c))
+            }
+            Re::AnyOf(ref chars) => {
+                let cases = chars.iter().flat_map(|&c| other_cases(c));
+                Re::one_of(chars.iter().copied().chain(cases))
+            }
+            // every case of every `char` in the set is left out
+            Re::NotAnyOf(ref chars) => {
+                let cases = chars.iter().flat_map(|&c| other_cases(c));
+                Re::none_of(chars.iter().copied().chain(cases))
+            }
+            Re::Range(lo, hi) => {
+                // the cases that aren't already in the range, grouped back into ranges
+                let cases: std::collections::BTreeSet<char> = (lo..=hi)
+                    .flat_map(other_cases)
+                    .filter(|d| !(lo..=hi).contains(d))
+                    .collect();
+                let mut ranges: Vec<(char, char)> = Vec::new();
+                for d in cases {
+                    match ranges.last_mut() {
+                        Some((_, end)) if (*end as u32) + 1 == d as u32 => *end = d,
+                        _ => ranges.push((d, d)),
+                    }
+                }
+                ranges
+                    .into_iter()
+                    .fold(Re::Range(lo, hi), |r, (lo, hi)| r.alt(Re::range(lo, hi)))
+            }
+            r => r,
+        })
+    }
+
+    /// Reverses the plan, so that it matches every string it used to match, backwards.
+    pub fn reverse(self) -> Self {
+        self.rewrite(|mut r| {
+            if let Re::Seq(r1, r2) = &mut r {
+                std::mem::swap(r1, r2);
+            }
+            r
+        })
+    }
+
+    /// Pulls a subexpression that both branches of an `Alt` start (or end) with out of the `Alt`,
+    /// e.g. `ab|ac` becomes `a(b|c)`, so that it is only derived once.
+    pub fn factor_common_affixes(self) -> Self {
+        self.rewrite(|mut r| {
+            let Re::Alt(r1, r2) = &mut r else {
+                return r;
+            };
+            let (Re::Seq(h1, t1), Re::Seq(h2, t2)) = (r1.as_mut(), r2.as_mut()) else {
+                return r;
+            };
+            if h1 == h2 {
+                take(h1).seq(take(t1).alt(take(t2)))
+            } else if t1 == t2 {
+                take(h1).alt(take(h2)).seq(take(t1))
+            } else {
+                r
+            }
+        })
+    }
+
+    /// Applies the simplifications that don't depend on the input: removing `Zero` from `Alt`s
+    /// and `One` from `Seq`s, collapsing `Seq`s and `And`s with a `Zero` in them, nested
+    /// repetitions and double complements, and dropping `Alt` branches that are already in the
+    /// same alternation, like the second `cat` in `(cat|dog)|cat`.
+    pub fn simplify(self) -> Self {
+        self.rewrite(|mut r| match &mut r {
+            Re::Alt(r1, r2) => match (r1.as_ref(), r2.as_ref()) {
+                (Re::Zero, _) => take(r2),
+                (_, Re::Zero) => take(r1),
+                (a, b) if branches(a).any(|a| a == b) => take(r1),
+                (a, b) if branches(b).any(|b| b == a) => take(r2),
+                _ => r,
+            },
+            Re::Seq(r1, r2) => match (r1.as_ref(), r2.as_ref()) {
+                (Re::Zero, _) | (_, Re::Zero) => Re::Zero,
+                (Re::One, _) => take(r2),
+                (_, Re::One) => take(r1),
+                _ => r,
+            },
+            Re::And(r1, r2) => match (r1.as_ref(), r2.as_ref()) {
+                (Re::Zero, _) | (_, Re::Zero) => Re::Zero,
+                (a, b) if a == b => take(r1),
+                _ => r,
+            },
+            Re::Star(r1) => match r1.as_mut() {
+                Re::Zero | Re::One => Re::One,
+                Re::Star(_) => take(r1),
+                // (r+)* == r*
+                Re::Plus(r11) => take(r11).star(),
+                _ => r,
+            },
+            Re::Plus(r1) => match r1.as_ref() {
+                Re::Zero | Re::One | Re::Star(_) | Re::Plus(_) => take(r1),
+                _ => r,
+            },
+            Re::Opt(r1) => match r1.as_mut() {
+                Re::Zero | Re::One => Re::One,
+                Re::Star(_) | Re::Opt(_) => take(r1),
+                // (r+)? == r*
+                Re::Plus(r11) => take(r11).star(),
+                _ => r,
+            },
+            Re::Not(r1) => match r1.as_mut() {
+                // !!r == r
+                Re::Not(r11) => take(r11),
+                _ => r,
+            },
+            _ => r,
+        })
+    }
+
+    pub fn into_plan(self) -> Re {
+        self.plan
+    }
+
+    pub fn compile(self) -> Regex<'static> {
+        Regex::from(&self.plan)
+    }
+}
+
+/// The lower and upper case forms of `c` that are a single `char`.
+fn other_cases(c: char) -> impl Iterator<Item = char> {
+    let lower: Vec<char> = c.to_lowercase().collect();
+    let upper: Vec<char> = c.to_uppercase().collect();
+    [lower, upper]
+        .into_iter()
+        .filter_map(|case| match case[..] {
+            [d] => Some(d),
+            _ => None,
+        })
+}
+
+/// Every branch of the alternation `r`, with nested `Alt`s flattened. Just `r` if it isn't an
+/// `Alt`.
+fn branches(r: &Re) -> impl Iterator<Item = &Re> {
+    let mut stack = vec![r];
+    std::iter::from_fn(move || loop {
+        match stack.pop()? {
+            Re::Alt(r1, r2) => {
+                stack.push(r2);
+                stack.push(r1);
+            }
+            r => return Some(r),
+        }
+    })
+}
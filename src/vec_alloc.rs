@@ -1,28 +1,28 @@
-use std::alloc::{Allocator, Global, Layout};
-use std::mem::transmute;
+use std::alloc::{AllocError, Allocator, Global, Layout};
 use std::ptr::NonNull;
 use std::{fmt, ptr};
 
-struct RawBuf<T> {
+struct RawBuf<T, A: Allocator = Global> {
     data: NonNull<[T]>,
+    /// The exact layout we allocated `data` with, so that we can deallocate with it too.
+    layout: Layout,
+    alloc: A,
 }
 
-impl<T> Drop for RawBuf<T> {
+impl<T, A: Allocator> Drop for RawBuf<T, A> {
     fn drop(&mut self) {
+        // SAFETY: `data` was allocated by `self.alloc` with exactly `layout`
         unsafe {
-            Global.deallocate(
-                transmute(self.data.as_non_null_ptr()),
-                // SAFETY: this might leak memory, due to rounding errors created in setup. Will
-                // have to check this. TODO
-                Self::new_layout(self.data.len()).0,
-            )
+            self.alloc
+                .deallocate(self.data.as_non_null_ptr().cast(), self.layout)
         }
     }
 }
 
-impl<T> fmt::Debug for RawBuf<T> {
+impl<T, A: Allocator> fmt::Debug for RawBuf<T, A> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         #[derive(Debug)]
+        #[allow(dead_code)]
         struct RawBuf {
             ptr: *const (),
             capacity: usize,
@@ -37,25 +37,28 @@ impl<T> fmt::Debug for RawBuf<T> {
     }
 }
 
-impl<T> RawBuf<T> {
-    fn new_layout(capacity: usize) -> (Layout, usize) {
-        Layout::new::<T>().repeat(capacity).unwrap()
+impl<T, A: Allocator> RawBuf<T, A> {
+    /// Create a new buffer with space for exactly `capacity` values, allocated by `alloc`.
+    ///
+    /// ## Panics
+    /// If `capacity` values don't fit in memory, or `alloc` fails, see `try_new_in`.
+    pub fn new_in(capacity: usize, alloc: A) -> Self {
+        Self::try_new_in(capacity, alloc).expect("buffer too large to allocate")
     }
 
-    /// Create a new
-    pub fn new(capacity: usize) -> Self {
-        let (layout, offset) = Self::new_layout(capacity);
-        let data = Global.allocate(layout).unwrap();
-        // SAFETY:
-        //     not actually verified, but from my tests, we seem to produce the correct
-        //     `Layout` so it should be fine. Maybe it won't be... We'll see. Obviously, just
-        //     the creation of this pointer is valid and safe, because it's essentially just a
-        //     cast.
-        let data = NonNull::slice_from_raw_parts(
-            unsafe { transmute(data.as_non_null_ptr()) },
-            data.len() / offset,
-        );
-        Self { data }
+    /// Like `new_in`, but returns an error if `capacity` values are too many bytes to describe,
+    /// or `alloc` can't hand them out.
+    pub fn try_new_in(capacity: usize, alloc: A) -> Result<Self, AllocError> {
+        let layout = Layout::array::<T>(capacity).map_err(|_| AllocError)?;
+        // The allocator might hand us more bytes than we asked for, but we ignore them. That way
+        // the capacity never depends on rounding and we can always deallocate with `layout`.
+        let data = alloc.allocate(layout)?;
+        let data = NonNull::slice_from_raw_parts(data.as_non_null_ptr().cast(), capacity);
+        Ok(Self {
+            data,
+            layout,
+            alloc,
+        })
     }
 
     /// ## Safety
@@ -84,25 +87,85 @@ impl<T> RawBuf<T> {
 
 /// Hands out NonNull<T>, packed allocation. Resizable, but previously created pointers will
 /// dangle.
-pub struct VecAlloc<T> {
-    buf: RawBuf<T>,
+///
+/// The buffer is allocated by `A`. Every new buffer (e.g. on `resize`) comes from a clone of it.
+pub struct VecAlloc<T, A: Allocator = Global> {
+    buf: RawBuf<T, A>,
     len: usize,
 }
 
-impl<T> fmt::Debug for VecAlloc<T> {
+impl<T, A: Allocator> fmt::Debug for VecAlloc<T, A> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "VecAlloc {{ buf: {:?}, len: {} }}", self.buf, self.len)
     }
 }
 
+impl<T, A: Allocator> Drop for VecAlloc<T, A> {
+    fn drop(&mut self) {
+        self.drop_values();
+    }
+}
+
+impl<T, A: Allocator> VecAlloc<T, A> {
+    /// Every allocated value, in the order they were allocated.
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the first `len` values are initialised, and they can't be freed or moved while
+        // `self` is borrowed
+        unsafe { std::slice::from_raw_parts(self.buf.data.as_non_null_ptr().as_ptr(), self.len) }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+
+    /// Drops every allocated value, and forgets them.
+    fn drop_values(&mut self) {
+        let len = std::mem::replace(&mut self.len, 0);
+        if std::mem::needs_drop::<T>() {
+            // SAFETY: the first `len` values are initialised, and we forget them before dropping
+            // them, so that a panicking destructor can't lead to a double drop
+            unsafe {
+                ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                    self.buf.data.as_mut_ptr(),
+                    len,
+                ))
+            };
+        }
+    }
+}
+
 impl<T> VecAlloc<T> {
     pub fn new(capacity: usize) -> Self {
+        Self::new_in(capacity, Global)
+    }
+}
+
+impl<T, A: Allocator + Clone> VecAlloc<T, A> {
+    /// Like `new`, but the buffer is allocated by `alloc`.
+    pub fn new_in(capacity: usize, alloc: A) -> Self {
         Self {
-            buf: RawBuf::new(capacity),
+            buf: RawBuf::new_in(capacity, alloc),
             len: 0,
         }
     }
 
+    /// What an empty buffer, like the one a child `Regex` starts with, grows to on `resize` and
+    /// `resize_preserving`. Doubling would leave it empty forever.
+    pub const MIN_CAPACITY: usize = 32;
+
+    fn new_buf(&self, capacity: usize) -> RawBuf<T, A> {
+        RawBuf::new_in(capacity, self.buf.alloc.clone())
+    }
+
+    /// The capacity to grow to: twice the current one, or `MIN_CAPACITY` for an empty buffer.
+    /// Errors if doubling overflows.
+    fn grown_capacity(&self) -> Result<usize, AllocError> {
+        match self.capacity() {
+            0 => Ok(Self::MIN_CAPACITY),
+            capacity => capacity.checked_mul(2).ok_or(AllocError),
+        }
+    }
+
     /// 'Allocate' a new value on this `VecAlloc`. It will be most local to the most-recently
     /// allocated value.
     ///
@@ -142,13 +205,401 @@ impl<T> VecAlloc<T> {
         self.len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Drops every allocation, but keeps the buffer around for reuse. Just like `resize`, this
+    /// invalidates every pointer handed out so far.
+    pub fn clear(&mut self) {
+        self.drop_values();
+    }
+
+    /// Doubles the capacity and drops every allocation, see `resize_preserving` to keep them. An
+    /// empty buffer grows to `MIN_CAPACITY` instead.
+    ///
+    /// ## Panics
+    /// If the new buffer can't be allocated, see `try_resize`.
     pub fn resize(&mut self) {
-        self.buf = RawBuf::new(self.capacity() * 2);
-        self.len = 0;
+        self.try_resize().expect("buffer too large to allocate")
+    }
+
+    /// Like `resize`, but returns an error instead of panicking if the doubled capacity overflows,
+    /// or the new buffer can't be allocated. In that case nothing is dropped, and pointers handed
+    /// out so far stay valid.
+    pub fn try_resize(&mut self) -> Result<(), AllocError> {
+        let buf = RawBuf::try_new_in(self.grown_capacity()?, self.buf.alloc.clone())?;
+        self.drop_values();
+        self.buf = buf;
+        Ok(())
+    }
+
+    /// Doubles the capacity, moving every allocated value into the new buffer. The `i`-th value
+    /// allocated can still be found with `self.get(i)`. An empty buffer grows to `MIN_CAPACITY`,
+    /// like for `resize`.
+    ///
+    /// ## Safety
+    /// Just like `resize`, every pointer handed out so far dangles afterwards, since the values
+    /// now live in a different buffer. Pointers should be rebased by index.
+    pub fn resize_preserving(&mut self) {
+        let capacity = self.grown_capacity().expect("buffer too large to allocate");
+        self.grow_to(capacity);
+    }
+
+    /// Moves every allocated value into a new buffer of exactly `capacity`.
+    fn grow_to(&mut self, capacity: usize) {
+        debug_assert!(capacity >= self.len);
+        let buf = self.new_buf(capacity);
+        // SAFETY: the new buffer has room for at least `self.len` values, and is a different
+        // allocation to the old one
+        unsafe {
+            ptr::copy_nonoverlapping(self.buf.data.as_mut_ptr(), buf.data.as_mut_ptr(), self.len)
+        };
+        self.buf = buf;
+    }
+
+    /// Makes sure that at least `additional` more values can be allocated without running out of
+    /// space, growing the buffer if needed. Like `resize_preserving`, the values are kept, but if
+    /// the buffer grows, every pointer handed out so far dangles.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self
+            .len
+            .checked_add(additional)
+            .expect("buffer too large to allocate");
+        if needed > self.capacity() {
+            self.grow_to(needed.max(self.capacity().saturating_mul(2)));
+        }
     }
 
     pub fn resized(&mut self) -> &mut Self {
         self.resize();
         self
     }
+
+    /// The value allocated `index`-th, or `None` if fewer values than that have been allocated.
+    pub fn get(&mut self, index: usize) -> Option<NonNull<T>> {
+        if index < self.len {
+            self.buf.get(index)
+        } else {
+            None
+        }
+    }
+
+    /// The index that `ptr` was allocated at, or `None` if it doesn't point at a value allocated
+    /// on `self`. Never dereferences `ptr`.
+    pub fn index_of(&self, ptr: NonNull<T>) -> Option<usize> {
+        let base = self.buf.data.as_non_null_ptr().as_ptr() as usize;
+        let offset = (ptr.as_ptr() as usize).checked_sub(base)?;
+        let size = std::mem::size_of::<T>().max(1);
+        let index = offset / size;
+        (offset % size == 0 && index < self.len).then_some(index)
+    }
+
+    /// A bitwise copy of every allocated value, in a new buffer of the same capacity. Pointers
+    /// between the values still point into `self`, so they have to be rebased (e.g. with
+    /// `index_of`) before the copy can stand on its own.
+    pub fn duplicate(&self) -> Self
+    where
+        T: Copy,
+    {
+        let mut copy = Self::new_in(self.capacity(), self.buf.alloc.clone());
+        // SAFETY: the new buffer has the same capacity, and is a different allocation
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.buf.data.as_non_null_ptr().as_ptr(),
+                copy.buf.data.as_mut_ptr(),
+                self.len,
+            )
+        };
+        copy.len = self.len;
+        copy
+    }
+
+    /// Moves every value allocated on `other` onto the end of `self`, and returns the index that
+    /// the first of them ends up at. The `i`-th value of `other` can then be found with
+    /// `self.get(base + i)`.
+    ///
+    /// ## Safety
+    /// - Every pointer into `other` is invalidated, just like when it is dropped.
+    /// - If `self` doesn't have room for everything, it grows, which invalidates every pointer into
+    ///   `self` too, just like `resize` (but the values themselves are kept). Pointers should be
+    ///   rebased by index in that case.
+    pub fn merge<B: Allocator + Clone>(&mut self, mut other: VecAlloc<T, B>) -> usize {
+        let base = self.len;
+        let len = self.len + other.len;
+        if len > self.capacity() {
+            self.grow_to(len.max(self.capacity().saturating_mul(2)));
+        }
+        // SAFETY: `self.buf` has room for `base + other.len` values, and `other` is a different
+        // allocation. The values are moved, `other`'s buffer never drops them.
+        unsafe {
+            ptr::copy_nonoverlapping(
+                other.buf.data.as_mut_ptr(),
+                self.buf.data.as_mut_ptr().add(base),
+                other.len,
+            )
+        };
+        other.len = 0;
+        self.len = len;
+        base
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::alloc::AllocError;
+    use std::cell::{Cell, RefCell};
+
+    /// Counts what `Global` is asked for and given back through it, and checks that every block is
+    /// given back with the layout it was allocated with.
+    #[derive(Default)]
+    struct Counting {
+        allocations: Cell<usize>,
+        live_bytes: Cell<usize>,
+        /// Zero-sized blocks can share a pointer, so this is a list rather than a map.
+        layouts: RefCell<Vec<(NonNull<u8>, Layout)>>,
+    }
+
+    unsafe impl Allocator for &Counting {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.allocations.set(self.allocations.get() + 1);
+            self.live_bytes.set(self.live_bytes.get() + layout.size());
+            let ptr = Global.allocate(layout)?;
+            self.layouts
+                .borrow_mut()
+                .push((ptr.as_non_null_ptr(), layout));
+            Ok(ptr)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            let mut layouts = self.layouts.borrow_mut();
+            let i = layouts
+                .iter()
+                .position(|&block| block == (ptr, layout))
+                .expect("deallocated with a different layout");
+            layouts.swap_remove(i);
+            self.live_bytes.set(self.live_bytes.get() - layout.size());
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    fn alloc_all<T: Copy + PartialEq + fmt::Debug>(capacity: usize, value: T) {
+        let mut alloc = VecAlloc::new(capacity);
+        assert_eq!(alloc.capacity(), capacity);
+        let ptrs: Vec<_> = (0..capacity).map(|_| alloc.alloc(value).unwrap()).collect();
+        assert!(alloc.alloc(value).is_err());
+        for ptr in ptrs {
+            assert_eq!(unsafe { ptr.as_ptr().read() }, value);
+        }
+    }
+
+    // Worth running under Miri, which checks that every buffer is deallocated with the layout it
+    // was allocated with.
+    #[test]
+    fn odd_capacities() {
+        for capacity in [0, 1, 3, 5, 7, 13, 31, 33, 100] {
+            alloc_all(capacity, 7u8);
+            alloc_all(capacity, 7u16);
+            alloc_all(capacity, (7u8, 7u32));
+            alloc_all(capacity, [7u8; 3]);
+            alloc_all(capacity, ());
+        }
+    }
+
+    // Also worth running under Miri. `Global` may hand out more bytes than asked for, which must
+    // not change the layout a buffer is given back with.
+    #[test]
+    fn alloc_and_drop_repeatedly() {
+        let counting = Counting::default();
+        for capacity in [0, 1, 3, 7, 13, 33] {
+            for _ in 0..10 {
+                let mut a = VecAlloc::new_in(capacity, &counting);
+                while a.alloc((1u8, 2u32)).is_ok() {}
+                a.resize_preserving();
+                a.resize();
+                let mut b = VecAlloc::new_in(capacity, &counting);
+                while b.alloc([3u8; 3]).is_ok() {}
+                drop(b.duplicate());
+            }
+        }
+        assert_eq!(counting.live_bytes.get(), 0);
+        assert!(counting.layouts.borrow().is_empty());
+    }
+
+    #[test]
+    fn resize_overflow() {
+        // doubling an empty buffer would leave it empty
+        let mut alloc = VecAlloc::<u32>::new(0);
+        assert!(alloc.alloc(1).is_err());
+        alloc.resize();
+        assert_eq!(alloc.capacity(), VecAlloc::<u32>::MIN_CAPACITY);
+        assert!(alloc.alloc(1).is_ok());
+
+        // zero-sized values take no bytes, so any capacity fits, but it can't be doubled
+        let mut alloc = VecAlloc::<()>::new(usize::MAX);
+        alloc.alloc(()).unwrap();
+        assert!(alloc.try_resize().is_err());
+        assert_eq!((alloc.len(), alloc.capacity()), (1, usize::MAX));
+
+        // too many bytes for a `Layout`
+        assert!(RawBuf::<u64>::try_new_in(usize::MAX / 4, Global).is_err());
+    }
+
+    #[test]
+    fn resize_preserving() {
+        let read = |alloc: &mut VecAlloc<u32>, i| unsafe { alloc.get(i).unwrap().as_ptr().read() };
+
+        let mut alloc = VecAlloc::new(5);
+        for i in 0..5 {
+            alloc.alloc(i * 10).unwrap();
+        }
+        assert!(alloc.alloc(50).is_err());
+        alloc.resize_preserving();
+        assert_eq!((alloc.len(), alloc.capacity()), (5, 10));
+        assert_eq!(
+            (0..5).map(|i| read(&mut alloc, i)).collect::<Vec<_>>(),
+            [0, 10, 20, 30, 40]
+        );
+        alloc.alloc(50).unwrap();
+        assert_eq!(read(&mut alloc, 5), 50);
+
+        // an empty allocator still grows
+        let mut alloc = VecAlloc::<u32>::new(0);
+        alloc.resize_preserving();
+        assert_eq!(alloc.capacity(), VecAlloc::<u32>::MIN_CAPACITY);
+    }
+
+    #[test]
+    fn iter() {
+        let mut alloc = VecAlloc::new(4);
+        assert!(alloc.as_slice().is_empty());
+        for i in 0..3u32 {
+            alloc.alloc(i * 10).unwrap();
+        }
+        assert_eq!(alloc.as_slice(), [0, 10, 20]);
+        alloc.resize_preserving();
+        assert_eq!(alloc.iter().copied().collect::<Vec<_>>(), [0, 10, 20]);
+        alloc.clear();
+        assert_eq!(alloc.iter().count(), 0);
+    }
+
+    #[test]
+    fn reserve() {
+        let mut alloc = VecAlloc::new(4);
+        alloc.reserve(4);
+        assert_eq!(alloc.capacity(), 4);
+        alloc.alloc(1u32).unwrap();
+        alloc.reserve(3);
+        assert_eq!(alloc.capacity(), 4);
+        // grows to at least double, keeping what is there
+        alloc.reserve(4);
+        assert_eq!(alloc.capacity(), 8);
+        alloc.reserve(20);
+        assert_eq!(alloc.capacity(), 21);
+        assert_eq!(unsafe { alloc.get(0).unwrap().as_ptr().read() }, 1);
+    }
+
+    #[test]
+    fn drops_every_value_once() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct Counted(Rc<Cell<usize>>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Rc::new(Cell::new(0));
+        let counted = || Counted(drops.clone());
+
+        let mut alloc = VecAlloc::new(4);
+        for _ in 0..3 {
+            assert!(alloc.alloc(counted()).is_ok());
+        }
+        drop(alloc);
+        assert_eq!(drops.get(), 3);
+
+        drops.set(0);
+        let mut alloc = VecAlloc::new(2);
+        for _ in 0..2 {
+            assert!(alloc.alloc(counted()).is_ok());
+        }
+        // the value that doesn't fit is handed back, and dropped by us
+        drop(alloc.alloc(counted()));
+        assert_eq!(drops.get(), 1);
+        alloc.resize();
+        assert_eq!(drops.get(), 3);
+        assert!(alloc.alloc(counted()).is_ok());
+        alloc.clear();
+        assert_eq!(drops.get(), 4);
+
+        // moved values are only dropped by the allocator they end up in
+        drops.set(0);
+        let mut a = VecAlloc::new(1);
+        let mut b = VecAlloc::new(2);
+        assert!(a.alloc(counted()).is_ok());
+        assert!(b.alloc(counted()).is_ok());
+        assert!(b.alloc(counted()).is_ok());
+        a.merge(b);
+        a.resize_preserving();
+        assert_eq!(drops.get(), 0);
+        drop(a);
+        assert_eq!(drops.get(), 3);
+    }
+
+    #[test]
+    fn custom_allocator() {
+        let counting = Counting::default();
+        let mut a = VecAlloc::new_in(3, &counting);
+        for i in 0..3u64 {
+            a.alloc(i).unwrap();
+        }
+        a.resize_preserving();
+        let b = a.duplicate();
+        assert_eq!(a.merge(b), 3);
+        a.resize();
+        assert_eq!(a.capacity(), 12);
+        // every buffer came from `counting`, and every one but the last has been given back
+        assert_eq!(counting.allocations.get(), 4);
+        assert_eq!(counting.live_bytes.get(), 12 * 8);
+        drop(a);
+        assert_eq!(counting.live_bytes.get(), 0);
+    }
+
+    #[test]
+    fn merge() {
+        let read = |alloc: &mut VecAlloc<u32>, i| unsafe { alloc.get(i).unwrap().as_ptr().read() };
+
+        // fits without growing
+        let mut a = VecAlloc::new(8);
+        let mut b = VecAlloc::new(3);
+        for i in 0..3 {
+            a.alloc(i).unwrap();
+            b.alloc(10 + i).unwrap();
+        }
+        let base = a.merge(b);
+        assert_eq!((base, a.len(), a.capacity()), (3, 6, 8));
+        assert_eq!(
+            (0..6).map(|i| read(&mut a, i)).collect::<Vec<_>>(),
+            [0, 1, 2, 10, 11, 12]
+        );
+        assert!(a.get(6).is_none());
+
+        // has to grow
+        let mut c = VecAlloc::new(5);
+        for i in 0..5 {
+            c.alloc(20 + i).unwrap();
+        }
+        let base = a.merge(c);
+        assert_eq!((base, a.len()), (6, 11));
+        assert!(a.capacity() >= 11);
+        assert_eq!(read(&mut a, 2), 2);
+        assert_eq!(read(&mut a, base + 4), 24);
+
+        let base = a.merge(VecAlloc::new(0));
+        assert_eq!((base, a.len()), (11, 11));
+    }
 }